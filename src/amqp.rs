@@ -0,0 +1,211 @@
+use crate::cli::Opts;
+use crate::publisher::ResultPublisher;
+use futures_util::stream::{self, BoxStream, SelectAll, StreamExt};
+use lapin::{
+    message::Delivery,
+    options::*,
+    types::{AMQPValue, FieldTable, ShortString},
+    Channel, Connection, ConnectionProperties, ExchangeKind,
+};
+use rand::Rng;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// An open connection to the AMQP server, consuming from the judge queues.
+pub struct AmqpSession {
+    // The connection is kept so that it is not closed while the channel is in use.
+    pub connection: Connection,
+    pub channel: Channel,
+    /// The channel failed deliveries are republished on. It is in confirm mode, so that a
+    /// delivery is only acknowledged once its copy has been confirmed, and used by one
+    /// republish at a time, so that the confirms waited for are those of that copy alone.
+    pub republish_channel: Arc<Mutex<Channel>>,
+    /// The deliveries of all judge queues, tagged with the queue they come from.
+    pub consumer: SelectAll<BoxStream<'static, (String, Result<Delivery, lapin::Error>)>>,
+    pub publisher: Option<ResultPublisher>,
+}
+
+/// Exponential backoff with jitter for reconnecting to the AMQP server.
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(opts: &Opts) -> Backoff {
+        Backoff {
+            initial: Duration::from_millis(opts.reconnect_delay),
+            max: Duration::from_millis(opts.max_reconnect_delay),
+            attempt: 0,
+        }
+    }
+
+    /// Get the delay before the next attempt. The delay doubles on every attempt up to the
+    /// maximum, and is randomized to between half and all of it so that controllers do not all
+    /// reconnect at the same moment.
+    pub fn next_delay(&mut self) -> Duration {
+        let factor = 2u32.saturating_pow(self.attempt.min(16));
+        let delay = (self.initial * factor).min(self.max);
+        self.attempt += 1;
+
+        delay.mul_f64(rand::thread_rng().gen_range(0.5, 1.0))
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// Connect to the AMQP server, set up the judge queues and start consuming from them.
+pub async fn connect(opts: &Opts, queues: &[String]) -> Result<AmqpSession, lapin::Error> {
+    let amqp_url = opts.amqp_url.as_ref().expect("No AMQP URL given.");
+    let connection = Connection::connect(amqp_url, ConnectionProperties::default()).await?;
+
+    log::info!("Connected to AMQP server.");
+
+    let channel = connection.create_channel().await?;
+    channel
+        .basic_qos(opts.workers as u16, BasicQosOptions::default())
+        .await?;
+    if let Some(exchange) = &opts.exchange {
+        channel
+            .exchange_declare(
+                exchange,
+                parse_exchange_kind(&opts.exchange_type),
+                ExchangeDeclareOptions {
+                    durable: true,
+                    ..ExchangeDeclareOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+    }
+    for queue in queues {
+        declare_queue(opts, &channel, queue).await?;
+    }
+    if let Some(dead_letter_queue) = &opts.dead_letter_queue {
+        channel
+            .queue_declare(
+                dead_letter_queue,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..QueueDeclareOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+    }
+
+    let republish_channel = connection.create_channel().await?;
+    republish_channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await?;
+
+    let publisher = match &opts.result_exchange {
+        Some(exchange) => Some(ResultPublisher::declare(&connection, exchange).await?),
+        None => None,
+    };
+
+    log::info!("Starting consumer...");
+    let mut consumers = vec![];
+    for queue in queues {
+        let consumer = channel
+            .basic_consume(
+                queue,
+                &consumer_tag(opts, queue),
+                BasicConsumeOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+
+        // Tag the deliveries with their queue, so that failed ones can be put back there.
+        let queue = queue.clone();
+        consumers.push(
+            consumer
+                .map(move |delivery| (queue.clone(), delivery))
+                .boxed(),
+        );
+    }
+
+    Ok(AmqpSession {
+        connection,
+        channel,
+        republish_channel: Arc::new(Mutex::new(republish_channel)),
+        consumer: stream::select_all(consumers),
+        publisher,
+    })
+}
+
+/// The consumer tag for consuming from `queue`. The configured tag is used as it is for the main
+/// queue, and suffixed with the queue name for capability queues to keep it unique.
+pub fn consumer_tag(opts: &Opts, queue: &str) -> String {
+    if queue == opts.queue {
+        opts.consumer_tag.clone()
+    } else {
+        format!("{}.{}", opts.consumer_tag, queue)
+    }
+}
+
+/// Declare `queue` and bind it to the configured exchange. The main queue is bound with the
+/// configured routing keys, while capability queues are bound with their own name.
+async fn declare_queue(opts: &Opts, channel: &Channel, queue: &str) -> Result<(), lapin::Error> {
+    let mut arguments = FieldTable::default();
+    if let Some(max_priority) = opts.max_priority {
+        arguments.insert(
+            ShortString::from("x-max-priority"),
+            AMQPValue::ShortShortUInt(max_priority),
+        );
+    }
+
+    channel
+        .queue_declare(
+            queue,
+            QueueDeclareOptions {
+                durable: !opts.transient_queue,
+                exclusive: opts.exclusive_queue,
+                ..QueueDeclareOptions::default()
+            },
+            arguments,
+        )
+        .await?;
+    log::info!("Declared queue {}.", queue);
+
+    if let Some(exchange) = &opts.exchange {
+        let routing_keys = if queue != opts.queue || opts.routing_keys.is_empty() {
+            vec![queue.to_string()]
+        } else {
+            opts.routing_keys.clone()
+        };
+        for routing_key in &routing_keys {
+            channel
+                .queue_bind(
+                    queue,
+                    exchange,
+                    routing_key,
+                    QueueBindOptions::default(),
+                    FieldTable::default(),
+                )
+                .await?;
+            log::info!(
+                "Bound queue {} to exchange {} with routing key {}.",
+                queue,
+                exchange,
+                routing_key
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_exchange_kind(exchange_type: &str) -> ExchangeKind {
+    match exchange_type {
+        "direct" => ExchangeKind::Direct,
+        "fanout" => ExchangeKind::Fanout,
+        "topic" => ExchangeKind::Topic,
+        "headers" => ExchangeKind::Headers,
+        other => ExchangeKind::Custom(other.to_string()),
+    }
+}
@@ -14,10 +14,32 @@ pub struct PartialSubmission {
     pub source_code: String,
 }
 
+pub use crate::problem::ProblemMetadata;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ControllerRegistration {
+    pub name: String,
+    pub version: String,
+    pub sandboxes: i32,
+    pub workers: i32,
+    pub languages: Vec<String>,
+    pub interactive: bool,
+    pub max_memory: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegisteredController {
+    pub id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ControllerHeartbeat {
+    pub submissions: Vec<i32>,
+    pub draining: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
-pub struct ProblemMetadata {
-    #[serde(rename = "type")]
-    pub problem_type: String,
-    pub last_update: String,
-    pub testcases: Vec<serde_yaml::Value>,
+pub struct LeaseRequest {
+    pub controller: String,
+    pub duration: u64,
 }
@@ -0,0 +1,370 @@
+use chrono::{TimeZone, Utc};
+use futures_util::future::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+
+/// The number of bytes in a MB, as the cache size is given in.
+pub const MB: u64 = 1024 * 1024;
+
+/// The name of the manifest file in a problem folder.
+const MANIFEST: &str = "manifest.json";
+
+/// The name of the file in a problem folder recording when the problem was last used, in seconds
+/// since the Unix epoch.
+const LAST_USED: &str = "last-used";
+
+/// Per-problem locks guarding the problem folders. Downloading the resources of a problem takes
+/// the write lock, while judging a submission of the problem holds the read lock, so that workers
+/// never judge against a folder that is being replaced.
+#[derive(Clone, Default)]
+pub struct ProblemLocks {
+    locks: Arc<Mutex<HashMap<String, Arc<RwLock<()>>>>>,
+    /// The number of submissions being judged against each problem.
+    users: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+/// A claim on a problem by a submission being judged against it, which keeps the problem from
+/// being evicted from the cache until it is dropped.
+pub struct ProblemClaim {
+    problem_slug: String,
+    users: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl ProblemLocks {
+    pub fn new() -> ProblemLocks {
+        ProblemLocks::default()
+    }
+
+    /// Get the lock of the problem with slug `problem_slug`, creating it if necessary.
+    pub fn get(&self, problem_slug: &str) -> Arc<RwLock<()>> {
+        self.locks
+            .lock()
+            .unwrap()
+            .entry(problem_slug.to_string())
+            .or_insert_with(|| Arc::new(RwLock::new(())))
+            .clone()
+    }
+
+    /// Mark the problem with slug `problem_slug` as in use until the claim is dropped.
+    pub fn claim(&self, problem_slug: &str) -> ProblemClaim {
+        *self
+            .users
+            .lock()
+            .unwrap()
+            .entry(problem_slug.to_string())
+            .or_insert(0) += 1;
+        ProblemClaim {
+            problem_slug: problem_slug.to_string(),
+            users: self.users.clone(),
+        }
+    }
+
+    /// Whether a submission is being judged against the problem with slug `problem_slug`.
+    pub fn is_in_use(&self, problem_slug: &str) -> bool {
+        self.users.lock().unwrap().contains_key(problem_slug)
+    }
+}
+
+impl Drop for ProblemClaim {
+    fn drop(&mut self) {
+        let mut users = self.users.lock().unwrap();
+        if let Some(count) = users.get_mut(&self.problem_slug) {
+            *count -= 1;
+            if *count == 0 {
+                users.remove(&self.problem_slug);
+            }
+        }
+    }
+}
+
+/// The record of the resources cached in a problem folder. It is written once the resources are
+/// complete, so that a folder without a manifest is never judged against.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CacheManifest {
+    /// The version of the problem the resources belong to, as given by the server.
+    pub version: String,
+    /// The ETags the server sent with the resources, keyed by resource name.
+    #[serde(default)]
+    pub etags: BTreeMap<String, String>,
+}
+
+impl CacheManifest {
+    /// Read the manifest of the problem folder `folder`.
+    pub fn load(folder: &Path) -> Result<CacheManifest, String> {
+        let manifest = std::fs::read_to_string(folder.join(MANIFEST))
+            .map_err(|err| format!("Failed to read manifest: {}", err))?;
+        serde_json::from_str(&manifest).map_err(|err| format!("Invalid manifest: {}", err))
+    }
+
+    /// Write the manifest into the problem folder `folder`.
+    pub fn store(&self, folder: &Path) -> std::io::Result<()> {
+        let manifest = serde_json::to_string_pretty(self).expect("Failed to serialize manifest.");
+        std::fs::write(folder.join(MANIFEST), manifest)
+    }
+}
+
+/// Record that the problem in the folder `folder` has just been used.
+pub fn touch(folder: &Path) -> std::io::Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    std::fs::write(folder.join(LAST_USED), now.to_string())
+}
+
+/// When the problem in the folder `folder` was last used, in seconds since the Unix epoch.
+/// Problems which have never been recorded as used count as used at the epoch.
+fn last_used(folder: &Path) -> u64 {
+    std::fs::read_to_string(folder.join(LAST_USED))
+        .ok()
+        .and_then(|last_used| last_used.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// The total size in bytes of the files in `path`.
+fn disk_usage(path: &Path) -> u64 {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => std::fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| disk_usage(&entry.path()))
+                    .sum()
+            })
+            .unwrap_or(0),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }
+}
+
+/// A problem folder in the cache.
+#[derive(Debug)]
+struct CachedProblem {
+    problem_slug: String,
+    path: PathBuf,
+    /// When the problem was last used, in seconds since the Unix epoch.
+    last_used: u64,
+    /// The size of the folder in bytes.
+    size: u64,
+}
+
+/// Walk the cache in `folder`, returning the total size in bytes of its contents and the problem
+/// folders in it. Staging folders and the like are counted, but are not problem folders.
+fn scan(folder: &Path) -> std::io::Result<(u64, Vec<CachedProblem>)> {
+    let mut usage = 0;
+    let mut problems = vec![];
+    for entry in std::fs::read_dir(folder)?.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let size = disk_usage(&path);
+        usage += size;
+        let problem_slug = entry.file_name().to_string_lossy().into_owned();
+        if path.is_dir() && !problem_slug.starts_with('.') {
+            problems.push(CachedProblem {
+                problem_slug,
+                last_used: last_used(&path),
+                path,
+                size,
+            });
+        }
+    }
+    Ok((usage, problems))
+}
+
+/// Choose the problems to evict for the cache to take up at most `quota` bytes instead of
+/// `usage`, least recently used first. Problems in use are never chosen, so the cache may stay
+/// above the quota.
+fn plan_eviction(
+    mut usage: u64,
+    quota: u64,
+    mut problems: Vec<CachedProblem>,
+    locks: &ProblemLocks,
+) -> Vec<CachedProblem> {
+    problems.sort_by(|a, b| (a.last_used, &a.problem_slug).cmp(&(b.last_used, &b.problem_slug)));
+
+    let mut evicted = vec![];
+    for problem in problems {
+        if usage <= quota {
+            break;
+        }
+        if locks.is_in_use(&problem.problem_slug) {
+            continue;
+        }
+        usage = usage.saturating_sub(problem.size);
+        evicted.push(problem);
+    }
+    evicted
+}
+
+/// Evict the problems used least recently from the cache in `folder` until it takes up at most
+/// `quota` bytes. Problems in use are never evicted, so the cache may stay above the quota. The
+/// file system is accessed on the blocking thread pool, as walking a large cache takes a while.
+pub async fn enforce_quota(folder: &str, quota: u64, locks: &ProblemLocks) {
+    let cache_folder = PathBuf::from(folder);
+    let scanned = tokio::task::spawn_blocking(move || scan(&cache_folder))
+        .await
+        .expect("Problem cache scan panicked.");
+    let (mut usage, problems) = match scanned {
+        Ok(scanned) => scanned,
+        Err(err) => {
+            log::warn!("Failed to read the problem cache: {}", err);
+            return;
+        }
+    };
+
+    for problem in plan_eviction(usage, quota, problems, locks) {
+        // A submission claiming the problem from now on waits for the lock, and downloads the
+        // problem again once it finds it missing. The lock is only tried, as waiting for it would
+        // wait for the submissions of the problem being judged, and hold up new ones meanwhile.
+        let problem_lock = locks.get(&problem.problem_slug);
+        let _guard = match problem_lock.write().now_or_never() {
+            Some(guard) => guard,
+            None => continue,
+        };
+        if locks.is_in_use(&problem.problem_slug) || !problem.path.exists() {
+            continue;
+        }
+        let path = problem.path.clone();
+        let removed = tokio::task::spawn_blocking(move || std::fs::remove_dir_all(&path))
+            .await
+            .expect("Problem eviction panicked.");
+        match removed {
+            Ok(()) => {
+                usage = usage.saturating_sub(problem.size);
+                log::info!(
+                    "Evicted problem {} ({} MB, last used at {}) from the cache.",
+                    problem.problem_slug,
+                    problem.size / MB,
+                    Utc.timestamp(problem.last_used as i64, 0)
+                );
+            }
+            Err(err) => log::warn!("Failed to evict problem {}: {}", problem.problem_slug, err),
+        }
+    }
+
+    if usage > quota {
+        log::warn!(
+            "Problem cache takes up {} MB, exceeding the quota of {} MB, as the remaining problems are in use.",
+            usage / MB,
+            quota / MB
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problem(problem_slug: &str, last_used: u64, size: u64) -> CachedProblem {
+        CachedProblem {
+            problem_slug: problem_slug.to_string(),
+            path: PathBuf::from(problem_slug),
+            last_used,
+            size,
+        }
+    }
+
+    fn problems() -> Vec<CachedProblem> {
+        vec![
+            problem("a", 30, 10),
+            problem("b", 10, 10),
+            problem("c", 20, 10),
+            problem("d", 40, 10),
+        ]
+    }
+
+    fn slugs(problems: &[CachedProblem]) -> Vec<&str> {
+        problems
+            .iter()
+            .map(|problem| problem.problem_slug.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_problems_until_within_the_quota() {
+        let locks = ProblemLocks::new();
+
+        assert_eq!(
+            slugs(&plan_eviction(45, 25, problems(), &locks)),
+            vec!["b", "c"]
+        );
+        assert!(plan_eviction(45, 45, problems(), &locks).is_empty());
+    }
+
+    #[test]
+    fn never_evicts_problems_in_use() {
+        let locks = ProblemLocks::new();
+        let _claim = locks.claim("b");
+
+        assert_eq!(
+            slugs(&plan_eviction(45, 25, problems(), &locks)),
+            vec!["c", "a"]
+        );
+        assert_eq!(
+            slugs(&plan_eviction(45, 0, problems(), &locks)),
+            vec!["c", "a", "d"]
+        );
+    }
+
+    #[test]
+    fn evicts_problems_no_longer_in_use() {
+        let locks = ProblemLocks::new();
+        drop(locks.claim("b"));
+
+        assert_eq!(slugs(&plan_eviction(45, 35, problems(), &locks)), vec!["b"]);
+    }
+
+    /// Create a cache folder named `name` holding problems of 1000 bytes last used at the given
+    /// times.
+    fn cache_folder(name: &str, problems: &[(&str, u64)]) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!(
+            "judge-controller-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        for (problem_slug, last_used) in problems {
+            let path = folder.join(problem_slug);
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(path.join("testcase"), vec![0; 1000]).unwrap();
+            std::fs::write(path.join(LAST_USED), last_used.to_string()).unwrap();
+        }
+        folder
+    }
+
+    /// The problems left in the cache folder, which is removed.
+    fn remaining(folder: &Path) -> Vec<String> {
+        let mut remaining: Vec<String> = std::fs::read_dir(folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        remaining.sort();
+        std::fs::remove_dir_all(folder).unwrap();
+        remaining
+    }
+
+    #[tokio::test]
+    async fn enforces_the_quota_on_the_cache_folder() {
+        let folder = cache_folder("cache", &[("a", 30), ("b", 10), ("c", 20)]);
+        let locks = ProblemLocks::new();
+        let _claim = locks.claim("b");
+
+        enforce_quota(folder.to_str().unwrap(), 2100, &locks).await;
+
+        assert_eq!(remaining(&folder), vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn skips_problems_locked_by_submissions() {
+        let folder = cache_folder("locked-cache", &[("a", 30), ("b", 10), ("c", 20)]);
+        let locks = ProblemLocks::new();
+        let problem_lock = locks.get("b");
+        let _guard = problem_lock.read().await;
+
+        enforce_quota(folder.to_str().unwrap(), 2100, &locks).await;
+
+        assert_eq!(remaining(&folder), vec!["a", "b", "c"]);
+    }
+}
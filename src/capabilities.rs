@@ -0,0 +1,125 @@
+use crate::cli::Opts;
+use serde_yaml::Value;
+
+/// What the submissions judged on this host may require.
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    /// The languages listed in the language definition file.
+    pub languages: Vec<String>,
+    /// Whether interactive problems can be judged.
+    pub interactive: bool,
+    /// The maximum memory limit in MB a problem may have, if limited.
+    pub max_memory: Option<u64>,
+}
+
+impl Capabilities {
+    pub fn detect(opts: &Opts) -> Capabilities {
+        let definition = read_language_definition(opts);
+
+        Capabilities {
+            languages: language_entries(&definition)
+                .into_iter()
+                .map(|(code, _)| code)
+                .collect(),
+            interactive: !opts.no_interactive,
+            max_memory: opts.max_memory,
+        }
+    }
+
+    /// The queues to consume from when routing by capability. Jobs for a language are sent to
+    /// `<queue>.<language>`, and to `<queue>.<language>.interactive` if the problem is
+    /// interactive. The memory limit is not part of the queue name, so jobs exceeding the
+    /// maximum memory of this host are only turned away once received.
+    pub fn queues(&self, queue: &str) -> Vec<String> {
+        let mut queues = vec![];
+        for language in &self.languages {
+            queues.push(format!("{}.{}", queue, language));
+            if self.interactive {
+                queues.push(format!("{}.{}.interactive", queue, language));
+            }
+        }
+        queues
+    }
+
+    /// Check whether a submission with the given requirements can be judged on this host.
+    pub fn check(
+        &self,
+        language: &str,
+        interactive: bool,
+        memory_limit: Option<u64>,
+    ) -> Result<(), String> {
+        if !self.languages.iter().any(|supported| supported == language) {
+            return Err(format!("Language {} is not supported.", language));
+        }
+        if interactive && !self.interactive {
+            return Err("Interactive problems are not supported.".to_string());
+        }
+        if let (Some(memory_limit), Some(max_memory)) = (memory_limit, self.max_memory) {
+            if memory_limit > max_memory {
+                return Err(format!(
+                    "Memory limit of {} MB exceeds the maximum of {} MB.",
+                    memory_limit, max_memory
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Read the language definition file passed to the judge.
+pub fn read_language_definition(opts: &Opts) -> Value {
+    let definition = std::fs::read_to_string(&opts.language_definition)
+        .expect("Failed to read language definition.");
+    serde_yaml::from_str(&definition).expect("Failed to parse language definition.")
+}
+
+/// Collect the languages of a language definition with their codes. The definition is either a
+/// list of languages identified by `code`, `id` or `name`, or a mapping keyed by language code,
+/// optionally nested under `languages`.
+pub fn language_entries(definition: &Value) -> Vec<(String, &Value)> {
+    match definition {
+        Value::Sequence(languages) => languages
+            .iter()
+            .filter_map(|language| {
+                ["code", "id", "name"]
+                    .iter()
+                    .filter_map(|key| language.get(key).and_then(Value::as_str))
+                    .next()
+                    .map(|code| (code.to_string(), language))
+            })
+            .collect(),
+        Value::Mapping(languages) => match definition.get("languages") {
+            Some(languages) => language_entries(languages),
+            None => languages
+                .iter()
+                .filter_map(|(code, language)| Some((code.as_str()?.to_string(), language)))
+                .collect(),
+        },
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(definition: &str) -> Vec<String> {
+        let definition: Value = serde_yaml::from_str(definition).unwrap();
+        language_entries(&definition)
+            .into_iter()
+            .map(|(code, _)| code)
+            .collect()
+    }
+
+    #[test]
+    fn lists_the_languages_of_every_definition_shape() {
+        assert_eq!(
+            codes("[{ code: cpp }, { id: python3 }, { name: rust }, { extension: go }]"),
+            vec!["cpp", "python3", "rust"]
+        );
+        assert_eq!(codes("{ cpp: {}, python3: {} }"), vec!["cpp", "python3"]);
+        assert_eq!(codes("languages: [{ code: cpp }]"), vec!["cpp"]);
+        assert!(codes("cpp").is_empty());
+    }
+}
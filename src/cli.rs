@@ -1,10 +1,13 @@
 use clap::Clap;
 use log::LevelFilter;
 
+/// The version of the controller, shown by --version and reported to the judge server.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 /// Judge-Controller
 /// The controller between Judge-Server and MiniJudge-Rust
 #[derive(Clap, Clone)]
-#[clap(version = "0.0-alpha.1", author = "Southball")]
+#[clap(version = VERSION, author = "Southball")]
 pub struct Opts {
     /// The URL to the judge server.
     #[clap(long = "server")]
@@ -18,9 +21,22 @@ pub struct Opts {
     #[clap(long = "password")]
     pub password: String,
 
-    /// The URL to the AMQP server.
+    /// The source to take jobs from, one of amqp, http, spool and stdin. The http source requires
+    /// the judge server to list the pending submissions at submission/pending.
+    #[clap(long = "source", default_value = "amqp")]
+    pub source: String,
+
+    /// The URL to the AMQP server. Required for the amqp source.
     #[clap(long = "amqp-url")]
-    pub amqp_url: String,
+    pub amqp_url: Option<String>,
+
+    /// The folder to take jobs from for the spool source.
+    #[clap(long = "spool")]
+    pub spool: Option<String>,
+
+    /// The number of seconds to wait between polls of the http and spool sources.
+    #[clap(long = "poll-interval", default_value = "5")]
+    pub poll_interval: u64,
 
     /// The folder to store downloaded files.
     #[clap(long = "folder")]
@@ -34,11 +50,16 @@ pub struct Opts {
     #[clap(long = "judge")]
     pub judge: String,
 
-    /// The number of sandboxes to use.
+    /// The number of sandboxes to use. The sandboxes are split evenly between the workers.
     #[clap(long = "sandboxes")]
     pub sandboxes: i32,
 
-    /// The checker language to be passed to the judge.
+    /// The number of submissions to judge concurrently. More than one worker requires a judge
+    /// which supports --sandbox-offset, which is checked at startup.
+    #[clap(long = "workers", default_value = "1")]
+    pub workers: i32,
+
+    /// The language to compile C++ checkers and interactors, and those of unknown language, with.
     #[clap(long = "checker-language")]
     pub checker_language: String,
 
@@ -46,6 +67,121 @@ pub struct Opts {
     #[clap(long = "language-definition")]
     pub language_definition: String,
 
+    /// The queue to consume the submissions to judge from.
+    #[clap(long = "queue", default_value = "JUDGE_QUEUE")]
+    pub queue: String,
+
+    /// Whether the queue should be declared as transient instead of durable.
+    #[clap(long = "transient-queue")]
+    pub transient_queue: bool,
+
+    /// Whether the queue should be declared as exclusive to this controller.
+    #[clap(long = "exclusive-queue")]
+    pub exclusive_queue: bool,
+
+    /// The maximum priority supported by the queue. Submissions with higher priority are judged
+    /// first. The queue is declared without priorities if not given. The server orders messages
+    /// by their `priority` property, so publishers have to set it, as the `priority` of the job in
+    /// the body is only applied when a failed job is requeued.
+    #[clap(long = "max-priority")]
+    pub max_priority: Option<u8>,
+
+    /// The exchange to bind the queue to. The queue is only reachable through the default
+    /// exchange if no exchange is given.
+    #[clap(long = "exchange")]
+    pub exchange: Option<String>,
+
+    /// The type of the exchange, one of direct, fanout, topic and headers.
+    #[clap(long = "exchange-type", default_value = "direct")]
+    pub exchange_type: String,
+
+    /// The routing keys to bind the queue with. The queue name is used if none is given.
+    #[clap(long = "routing-key")]
+    pub routing_keys: Vec<String>,
+
+    /// The consumer tag to identify this controller with.
+    #[clap(long = "consumer-tag", default_value = "judge-controller")]
+    pub consumer_tag: String,
+
+    /// The exchange to publish verdicts and progress to. Results are only pushed through the
+    /// HTTP API if not given.
+    #[clap(long = "result-exchange")]
+    pub result_exchange: Option<String>,
+
+    /// Whether verdicts and progress should only be published to the result exchange instead of
+    /// also being pushed through the HTTP API.
+    #[clap(long = "disable-http-results")]
+    pub disable_http_results: bool,
+
+    /// The queue to route malformed or repeatedly failing deliveries to. Such deliveries are
+    /// rejected if no dead-letter queue is given.
+    #[clap(long = "dead-letter-queue")]
+    pub dead_letter_queue: Option<String>,
+
+    /// The number of times a submission is attempted before it is dead-lettered.
+    #[clap(long = "max-attempts", default_value = "3")]
+    pub max_attempts: i32,
+
+    /// The number of seconds to wait for in-flight submissions on shutdown before aborting and
+    /// requeueing them.
+    #[clap(long = "shutdown-timeout", default_value = "300")]
+    pub shutdown_timeout: u64,
+
+    /// The delay in milliseconds before the first attempt to reconnect to the AMQP server.
+    #[clap(long = "reconnect-delay", default_value = "1000")]
+    pub reconnect_delay: u64,
+
+    /// The maximum delay in milliseconds between attempts to reconnect to the AMQP server.
+    #[clap(long = "max-reconnect-delay", default_value = "60000")]
+    pub max_reconnect_delay: u64,
+
+    /// Whether to consume from one queue per supported language instead of a single queue, so
+    /// that only submissions this host can judge are received.
+    #[clap(long = "capability-routing")]
+    pub capability_routing: bool,
+
+    /// Whether interactive problems cannot be judged on this host.
+    #[clap(long = "no-interactive")]
+    pub no_interactive: bool,
+
+    /// The maximum memory limit in MB of problems judged on this host. The capability queues do
+    /// not tell memory limits apart, so submissions over the limit are still received with
+    /// `--capability-routing`, and are then given back to the queue for another host to judge.
+    #[clap(long = "max-memory")]
+    pub max_memory: Option<u64>,
+
+    /// The name to register this controller with on the judge server. The host name and process
+    /// ID are used if not given.
+    #[clap(long = "name")]
+    pub name: Option<String>,
+
+    /// Whether registering with the judge server and sending heartbeats should be skipped.
+    #[clap(long = "no-register")]
+    pub no_register: bool,
+
+    /// The number of seconds between heartbeats sent to the judge server.
+    #[clap(long = "heartbeat-interval", default_value = "30")]
+    pub heartbeat_interval: u64,
+
+    /// The number of seconds a submission may take to judge before the judge is stopped and a
+    /// System Error is reported. The judging is not limited if 0.
+    #[clap(long = "judge-timeout", default_value = "1800")]
+    pub judge_timeout: u64,
+
+    /// The number of seconds a lease on a submission lasts. The lease is renewed while the
+    /// submission is being judged.
+    #[clap(long = "lease-duration", default_value = "120")]
+    pub lease_duration: u64,
+
+    /// Whether submissions should be judged without acquiring a lease on them first.
+    #[clap(long = "no-lease")]
+    pub no_lease: bool,
+
+    /// The maximum size in MB of the problems cached in the resource folder. The problems used
+    /// least recently are evicted once it is exceeded. The cache is unbounded if not given.
+    #[clap(long = "cache-size")]
+    pub cache_size: Option<u64>,
+
     /// The level of verbosity.
     #[clap(short = "v", long = "verbose", parse(from_occurrences))]
     pub verbosity: i32,
@@ -57,11 +193,31 @@ pub struct Opts {
     /// Whether the log should be suppressed. This option overrides the verbose option.
     #[clap(short = "q", long = "quiet")]
     pub quiet: bool,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Clap, Clone)]
+pub enum Command {
+    /// Judge the given submissions directly instead of taking jobs from the source.
+    #[clap(name = "judge")]
+    Judge(JudgeCommand),
+}
+
+#[derive(Clap, Clone)]
+pub struct JudgeCommand {
+    /// The submissions to judge, given as IDs or inclusive ranges of IDs such as 1240-1260.
+    #[clap(required = true)]
+    pub submissions: Vec<String>,
 }
 
 pub fn debug_opts(opts: &Opts) {
     log::debug!("Server: {}", &opts.server);
     log::debug!("Folder: {}", &opts.folder);
+    log::debug!("Workers: {}", &opts.workers);
+    log::debug!("Source: {}", &opts.source);
+    log::debug!("Queue: {}", &opts.queue);
 }
 
 pub fn calc_log_level(verbosity: i32, quiet: bool) -> LevelFilter {
@@ -0,0 +1,135 @@
+use crate::cache::ProblemLocks;
+use crate::capabilities::Capabilities;
+use crate::cli::Opts;
+use crate::compile::Compilers;
+use crate::controller::{self, Context};
+use crate::job::Job;
+use crate::process::JudgeFlags;
+use crate::registry::Registry;
+use crate::shutdown;
+use crate::worker::Worker;
+use std::sync::Arc;
+
+/// Parse submission IDs given as single IDs or inclusive ranges such as `1240-1260`.
+pub fn parse_submission_ids(submissions: &[String]) -> Result<Vec<i32>, String> {
+    let mut submission_ids = vec![];
+
+    for submission in submissions {
+        let parse = |id: &str| {
+            id.trim()
+                .parse::<i32>()
+                .map_err(|err| format!("Invalid submission ID {:?}: {}", id, err))
+        };
+
+        match submission.find('-') {
+            Some(index) => {
+                let first = parse(&submission[..index])?;
+                let last = parse(&submission[index + 1..])?;
+                if first > last {
+                    return Err(format!("Invalid submission range {:?}.", submission));
+                }
+                submission_ids.extend(first..=last);
+            }
+            None => submission_ids.push(parse(submission)?),
+        }
+    }
+
+    Ok(submission_ids)
+}
+
+/// Judge the given submissions one by one and print a summary of their verdicts. Returns whether
+/// every submission has been judged successfully.
+pub async fn judge_submissions(opts: Arc<Opts>, submission_ids: &[i32]) -> bool {
+    // Abort the submission being judged right away on Ctrl-C, as there is nothing to drain.
+    let stop = shutdown::listen_for_signals();
+    let context = Context {
+        opts: opts.clone(),
+        locks: ProblemLocks::new(),
+        capabilities: Arc::new(Capabilities::detect(&opts)),
+        compilers: Arc::new(Compilers::detect(&opts)),
+        judge_flags: Arc::new(JudgeFlags::detect(&opts.judge)),
+        registry: Arc::new(Registry::new(&opts)),
+        publisher: None,
+        abort: stop.clone(),
+    };
+    let worker = Worker::standalone(&opts);
+
+    let mut rows = vec![];
+    for &submission_id in submission_ids {
+        if stop.is_fired() {
+            break;
+        }
+
+        log::info!("Judging submission {}...", submission_id);
+        let result = controller::process_submission(&context, &worker, &Job::new(submission_id))
+            .await
+            .map_err(|err| err.to_string());
+        rows.push((submission_id, result));
+    }
+
+    println!(
+        "{:>10}  {:<8}  {:>8}  {:>10}  Message",
+        "Submission", "Verdict", "Time", "Memory"
+    );
+    let mut success = rows.len() == submission_ids.len();
+    for (submission_id, result) in &rows {
+        match result {
+            Ok(verdict) => println!(
+                "{:>10}  {:<8}  {:>8.3}  {:>10}  {}",
+                submission_id,
+                verdict.verdict,
+                verdict.time,
+                verdict.memory,
+                verdict.compile_message.lines().next().unwrap_or("")
+            ),
+            Err(err) => {
+                success = false;
+                println!(
+                    "{:>10}  {:<8}  {:>8}  {:>10}  {}",
+                    submission_id, "-", "-", "-", err
+                );
+            }
+        }
+    }
+    if rows.len() < submission_ids.len() {
+        println!(
+            "Stopped after {} of {} submissions.",
+            rows.len(),
+            submission_ids.len()
+        );
+    }
+
+    success
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(submissions: &[&str]) -> Result<Vec<i32>, String> {
+        let submissions: Vec<String> = submissions.iter().map(|s| s.to_string()).collect();
+        parse_submission_ids(&submissions)
+    }
+
+    #[test]
+    fn parses_single_ids_and_ranges() {
+        assert_eq!(parse(&["1234"]), Ok(vec![1234]));
+        assert_eq!(
+            parse(&["1240-1243", "1250", " 1260 - 1261 "]),
+            Ok(vec![1240, 1241, 1242, 1243, 1250, 1260, 1261])
+        );
+        assert_eq!(parse(&["1240-1240"]), Ok(vec![1240]));
+    }
+
+    #[test]
+    fn rejects_reversed_ranges() {
+        assert!(parse(&["1260-1240"]).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_ids() {
+        for submission in &["", "abc", "12a4", "1240-", "-1240", "1240-1250-1260"] {
+            assert!(parse(&[submission]).is_err(), "{:?}", submission);
+        }
+    }
+}
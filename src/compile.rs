@@ -0,0 +1,154 @@
+use crate::capabilities;
+use crate::cli::Opts;
+use crate::error::ControllerError;
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The time a checker or an interactor may take to compile.
+const COMPILE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The compile commands of the languages in the language definition, used to precompile the
+/// checkers and interactors of problems. The command of a language is given by its
+/// `compile_command`, either as a list of arguments or as a single string, in which `{source}` and
+/// `{executable}` are replaced by the paths of the source and the executable. Languages without
+/// such a command are left to the judge to compile.
+#[derive(Debug, Default)]
+pub struct Compilers {
+    commands: HashMap<String, Vec<String>>,
+}
+
+impl Compilers {
+    pub fn detect(opts: &Opts) -> Compilers {
+        let definition = capabilities::read_language_definition(opts);
+        let commands: HashMap<String, Vec<String>> = capabilities::language_entries(&definition)
+            .into_iter()
+            .filter_map(|(code, language)| Some((code, parse_compile_command(language)?)))
+            .collect();
+
+        let mut languages: Vec<&str> = commands.keys().map(String::as_str).collect();
+        languages.sort();
+        log::info!("Languages precompiled: {:?}", languages);
+
+        Compilers { commands }
+    }
+
+    /// Whether programs in `language` are compiled before they are run.
+    pub fn is_compiled(&self, language: &str) -> bool {
+        self.commands.contains_key(language)
+    }
+
+    /// Compile the program at `source` in `language` to `executable`. The command is run in the
+    /// folder of the source, so that headers next to it such as `testlib.h` can be included.
+    pub async fn compile(
+        &self,
+        language: &str,
+        source: &Path,
+        executable: &Path,
+    ) -> Result<(), ControllerError> {
+        let command = self.commands.get(language).ok_or_else(|| {
+            ControllerError::Compile(format!("Language {} is not compiled.", language))
+        })?;
+        // The command runs in another folder, so relative paths would no longer resolve.
+        let current_dir =
+            std::env::current_dir().map_err(ControllerError::io("Failed to get current folder"))?;
+        let source = current_dir.join(source);
+        let executable = current_dir.join(executable);
+        let args: Vec<String> = command
+            .iter()
+            .map(|arg| {
+                arg.replace("{source}", source.to_str().unwrap())
+                    .replace("{executable}", executable.to_str().unwrap())
+            })
+            .collect();
+        log::info!("Compiling {} with: {}", source.display(), args.join(" "));
+
+        let output = tokio::process::Command::new(&args[0])
+            .args(&args[1..])
+            .current_dir(source.parent().unwrap_or_else(|| Path::new(".")))
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(COMPILE_TIMEOUT, output)
+            .await
+            .map_err(|_| {
+                ControllerError::Compile(format!(
+                    "Compiling {} did not finish within {} seconds.",
+                    source.display(),
+                    COMPILE_TIMEOUT.as_secs()
+                ))
+            })?
+            .map_err(ControllerError::io("Failed to start the compiler"))?;
+
+        if !output.status.success() || !executable.exists() {
+            return Err(ControllerError::Compile(format!(
+                "Compiling {} failed with {}: {}",
+                source.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// The path the executable of the program at `source` compiled in `language` is stored at. The
+/// executables are kept in the problem folder, so that they are replaced together with the
+/// sources when the problem is updated.
+pub fn executable_path(source: &Path, language: &str) -> PathBuf {
+    let name = source
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("program");
+    source
+        .with_file_name("compiled")
+        .join(format!("{}-{}", name, language))
+}
+
+fn parse_compile_command(language: &Value) -> Option<Vec<String>> {
+    let command: Vec<String> = match language.get("compile_command")? {
+        Value::Sequence(args) => args
+            .iter()
+            .filter_map(|arg| arg.as_str().map(String::from))
+            .collect(),
+        Value::String(command) => command.split_whitespace().map(String::from).collect(),
+        _ => return None,
+    };
+
+    if command.is_empty() {
+        None
+    } else {
+        Some(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(language: &str) -> Option<Vec<String>> {
+        parse_compile_command(&serde_yaml::from_str(language).unwrap())
+    }
+
+    #[test]
+    fn reads_the_compile_command_as_arguments_or_a_string() {
+        let expected = Some(vec![
+            "g++".to_string(),
+            "{source}".to_string(),
+            "-o".to_string(),
+            "{executable}".to_string(),
+        ]);
+
+        assert_eq!(
+            command("{ compile_command: [g++, '{source}', -o, '{executable}'] }"),
+            expected
+        );
+        assert_eq!(
+            command("{ compile_command: 'g++ {source} -o {executable}' }"),
+            expected
+        );
+        assert_eq!(command("{ compile_command: [] }"), None);
+        assert_eq!(command("{ execute_command: python3 }"), None);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::controller::Context;
+use crate::shutdown::{Trigger, TriggerHandle};
+use crate::source::{JobSource, ReceivedJob};
+use crate::worker::{Worker, WorkerPool};
+use std::future::Future;
+use std::time::Duration;
+
+/// Take jobs from `source` and process each of them with `handle` on an idle worker of `pool`,
+/// until the source is exhausted or `stop` is fired. The in-flight submissions are then given
+/// `--shutdown-timeout` seconds to finish before they are aborted through `abort`.
+///
+/// Returns whether every in-flight submission finished without being aborted.
+pub async fn run<H, F>(
+    context: Context,
+    mut source: Box<dyn JobSource>,
+    mut pool: WorkerPool,
+    mut stop: Trigger,
+    abort: TriggerHandle,
+    handle: H,
+) -> bool
+where
+    H: Fn(Context, Worker, ReceivedJob) -> F,
+    F: Future<Output = ()> + Send + 'static,
+{
+    loop {
+        // Wait for an idle worker before taking a job, so that a job is never held back while
+        // every worker is busy.
+        let worker = tokio::select! {
+            worker = pool.acquire() => worker,
+            _ = stop.fired() => break,
+        };
+
+        let received = tokio::select! {
+            received = source.next() => received,
+            _ = stop.fired() => None,
+        };
+        let received = match received {
+            Some(received) => received,
+            None => {
+                pool.release(worker);
+                break;
+            }
+        };
+        log::info!(
+            "Accepted request to process submission {} ({:?}, priority {}).",
+            received.job.submission_id,
+            received.job.kind,
+            received.job.priority
+        );
+
+        let context = Context {
+            publisher: source.publisher(),
+            ..context.clone()
+        };
+        let processing = handle(context, worker.clone(), received);
+        let mut releaser = pool.releaser();
+        tokio::spawn(async move {
+            processing.await;
+
+            releaser
+                .send(worker)
+                .await
+                .expect("Failed to release worker.");
+        });
+    }
+
+    source.stop().await;
+
+    if !stop.is_fired() {
+        log::info!("No more jobs. Waiting for in-flight submissions to finish...");
+        tokio::select! {
+            _ = pool.drain() => {}
+            _ = stop.fired() => {}
+        }
+    }
+
+    let shutdown_timeout = context.opts.shutdown_timeout;
+    log::info!(
+        "Waiting up to {}s for in-flight submissions to finish...",
+        shutdown_timeout
+    );
+    let drained = tokio::time::timeout(Duration::from_secs(shutdown_timeout), pool.drain())
+        .await
+        .is_ok();
+    if !drained {
+        log::warn!("In-flight submissions did not finish in time. Aborting them...");
+        abort.fire();
+        pool.drain().await;
+    }
+
+    source.close().await;
+
+    drained
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::Job;
+    use crate::shutdown;
+    use crate::source::{MemorySource, Outcome};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    fn settle_done(
+        _context: Context,
+        _worker: Worker,
+        received: ReceivedJob,
+    ) -> impl Future<Output = ()> {
+        received.ticket.settle(Outcome::Done)
+    }
+
+    #[tokio::test]
+    async fn processes_every_job_until_exhausted() {
+        let context = Context::for_tests(&[]);
+        let source = MemorySource::new((1..=5).map(Job::new).collect(), false);
+        let outcomes = source.outcomes();
+        let stopped = source.stopped();
+        let pool = WorkerPool::new(&context.opts);
+        let (_stop_handle, stop) = shutdown::trigger();
+        let (abort_handle, _abort) = shutdown::trigger();
+
+        let drained = run(
+            context,
+            Box::new(source),
+            pool,
+            stop,
+            abort_handle,
+            settle_done,
+        )
+        .await;
+
+        assert!(drained);
+        assert!(*stopped.lock().unwrap());
+        let mut outcomes = outcomes.lock().unwrap().drain(..).collect::<Vec<_>>();
+        outcomes.sort_by_key(|(submission_id, _)| *submission_id);
+        assert_eq!(
+            outcomes,
+            (1..=5)
+                .map(|submission_id| (submission_id, Outcome::Done))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn processes_at_most_one_job_per_worker() {
+        let context = Context::for_tests(&["--workers", "2"]);
+        let source = MemorySource::new((1..=6).map(Job::new).collect(), false);
+        let outcomes = source.outcomes();
+        let pool = WorkerPool::new(&context.opts);
+        let (_stop_handle, stop) = shutdown::trigger();
+        let (abort_handle, _abort) = shutdown::trigger();
+        let running = Arc::new(AtomicUsize::new(0));
+        let most_running = Arc::new(Mutex::new(0));
+
+        let handle = {
+            let running = running.clone();
+            let most_running = most_running.clone();
+            move |_context: Context, _worker: Worker, received: ReceivedJob| {
+                let running = running.clone();
+                let most_running = most_running.clone();
+                async move {
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    {
+                        let mut most_running = most_running.lock().unwrap();
+                        *most_running = (*most_running).max(now_running);
+                    }
+                    tokio::time::delay_for(Duration::from_millis(20)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    received.ticket.settle(Outcome::Done).await;
+                }
+            }
+        };
+        let drained = run(context, Box::new(source), pool, stop, abort_handle, handle).await;
+
+        assert!(drained);
+        assert_eq!(outcomes.lock().unwrap().len(), 6);
+        assert_eq!(*most_running.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn aborts_submissions_still_running_after_the_shutdown_timeout() {
+        let (abort_handle, abort) = shutdown::trigger();
+        let context = Context {
+            abort,
+            ..Context::for_tests(&["--shutdown-timeout", "0"])
+        };
+        let source = MemorySource::new(vec![Job::new(1)], true);
+        let outcomes = source.outcomes();
+        let stopped = source.stopped();
+        let pool = WorkerPool::new(&context.opts);
+        let (stop_handle, stop) = shutdown::trigger();
+
+        tokio::spawn(async move {
+            tokio::time::delay_for(Duration::from_millis(50)).await;
+            stop_handle.fire();
+        });
+        let handle = |context: Context, _worker: Worker, received: ReceivedJob| async move {
+            context.abort.clone().fired().await;
+            received.ticket.settle(Outcome::Aborted).await;
+        };
+        let drained = run(context, Box::new(source), pool, stop, abort_handle, handle).await;
+
+        assert!(!drained);
+        assert!(*stopped.lock().unwrap());
+        assert_eq!(*outcomes.lock().unwrap(), vec![(1, Outcome::Aborted)]);
+    }
+}
@@ -1,315 +1,184 @@
-use crate::api::*;
+use crate::cache::ProblemLocks;
+use crate::capabilities::Capabilities;
 use crate::cli::Opts;
-use crate::net::*;
+use crate::compile::Compilers;
+use crate::job::Job;
+use crate::process::JudgeFlags;
+use crate::publisher::ResultPublisher;
+use crate::registry::Registry;
 use crate::session::*;
-use chrono::{DateTime, Utc};
-use serde_json::json;
-use std::process::{Command, Stdio};
-use std::str::FromStr;
-use std::thread;
-
-pub async fn process_submission(
-    opts: &Opts,
-    submission_id: i32,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut session = Session::new(&opts.server);
-    session.init(&opts.username, &opts.password).await;
-
-    let client = reqwest::Client::new();
-    let submission_id_str = submission_id.to_string();
-
-    log::info!("Getting submission...");
-    let submission: PartialSubmission = client
-        .get(session.resolve(vec!["submission/", &submission_id_str]))
-        .bearer_auth(session.get_access_token().await)
-        .send()
-        .await
-        .unwrap()
-        .json::<ApiSuccess<PartialSubmission>>()
-        .await
-        .unwrap()
-        .data;
-    log::debug!("Submission: {:?}", submission);
-
-    log::info!("Getting problem...");
-    let problem: ProblemMetadata = client
-        .get(session.resolve(vec!["problem/", &submission.problem_slug]))
-        .bearer_auth(session.get_access_token().await)
-        .send()
-        .await
-        .unwrap()
-        .json::<ApiSuccess<ProblemMetadata>>()
-        .await
-        .unwrap()
-        .data;
-    log::debug!("Problem: {:?}", problem);
-
-    let is_problem_interactive = &problem.problem_type == "interactive";
-
-    let problem_base_url =
-        session.resolve(vec!["problem/", &format!("{}/", &submission.problem_slug)]);
-    let metadata_url = problem_base_url.join("metadata").unwrap();
-    let testcases_url = problem_base_url.join("testcases").unwrap();
-    let checker_url = problem_base_url.join("checker").unwrap();
-    let interactor_url = problem_base_url.join("interactor").unwrap();
-    let testlib_url = session.resolve_single("admin/testlib");
-
-    let resource_folder = std::path::PathBuf::from(&opts.folder).join(&submission.problem_slug);
-    let temp_folder = std::path::PathBuf::from(&opts.temp);
-
-    let testcases_path = resource_folder.join("testcases");
-    let checker_path = resource_folder.join("checker.cpp");
-    let interactor_path = resource_folder.join("interactor.cpp");
-    let metadata_path = resource_folder.join("metadata.yml");
-
-    let testcases_zip_path = temp_folder.join("testcases.zip");
-    let testlib_path = temp_folder.join("testlib.h");
-    let source_path = temp_folder.join("source");
-    let verdict_path = temp_folder.join("verdict.json");
-
-    std::fs::write(&source_path, &submission.source_code)?;
-
-    if verdict_path.exists() {
-        std::fs::remove_file(&verdict_path)?;
-    }
-
-    let should_download = {
-        if !resource_folder.exists() {
-            true
-        } else {
-            let last_download_str =
-                std::fs::read_to_string(resource_folder.join("last-update-time.txt"))?;
-            let last_download: DateTime<Utc> = DateTime::from_str(&last_download_str)?;
-            let last_update: DateTime<Utc> = DateTime::from_str(&problem.last_update)?;
-
-            log::info!("Last download: {}", last_download);
-            log::info!("Last update: {}", last_update);
-            last_download < last_update
-        }
-    };
+use crate::shutdown::Trigger;
+use crate::stage::{self, Judging, PublishVerdict, Stage, StageError};
+use crate::worker::Worker;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// The state shared by all submissions processed by the controller.
+#[derive(Clone)]
+pub struct Context {
+    pub opts: Arc<Opts>,
+    pub locks: ProblemLocks,
+    pub capabilities: Arc<Capabilities>,
+    pub compilers: Arc<Compilers>,
+    pub judge_flags: Arc<JudgeFlags>,
+    pub registry: Arc<Registry>,
+    pub publisher: Option<ResultPublisher>,
+    /// Fired when in-flight submissions should be abandoned because the controller is shutting
+    /// down.
+    pub abort: Trigger,
+}
 
-    if should_download {
-        // Delete and recreate folder if exists
-        if resource_folder.exists() {
-            std::fs::remove_dir_all(&resource_folder)?;
+#[cfg(test)]
+impl Context {
+    /// A context for tests, with the options parsed from the required arguments followed by
+    /// `args`. Every context gets folders of its own, so that tests can run concurrently.
+    pub fn for_tests(args: &[&str]) -> Context {
+        use clap::Clap;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static CONTEXTS: AtomicUsize = AtomicUsize::new(0);
+        let temp = std::env::temp_dir().join(format!(
+            "judge-controller-test-{}-{}",
+            std::process::id(),
+            CONTEXTS.fetch_add(1, Ordering::SeqCst)
+        ));
+        let temp = temp.to_str().unwrap();
+        let required = [
+            "judge-controller",
+            "--server",
+            "http://localhost/",
+            "--username",
+            "judge",
+            "--password",
+            "judge",
+            "--folder",
+            temp,
+            "--temp",
+            temp,
+            "--judge",
+            "minijudge-rust",
+            "--sandboxes",
+            "4",
+            "--checker-language",
+            "cpp",
+            "--language-definition",
+            "languages.yaml",
+            "--no-register",
+        ];
+        let opts = Opts::parse_from(required.iter().chain(args));
+
+        Context {
+            registry: Arc::new(Registry::new(&opts)),
+            opts: Arc::new(opts),
+            locks: ProblemLocks::new(),
+            capabilities: Arc::new(Capabilities {
+                languages: vec!["cpp".to_string(), "python3".to_string()],
+                interactive: true,
+                max_memory: None,
+            }),
+            compilers: Arc::new(Compilers::default()),
+            judge_flags: Arc::new(JudgeFlags::default()),
+            publisher: None,
+            abort: crate::shutdown::trigger().1,
         }
-        std::fs::create_dir_all(&resource_folder)?;
-
-        // Download testcases
-        std::fs::write(
-            resource_folder.join("last-update-time.txt"),
-            Utc::now().to_rfc3339(),
-        )?;
+    }
+}
 
-        // Download metadata, checker and testlib.h
-        download_to_file(
-            &client,
-            metadata_url,
-            &metadata_path,
-            session.get_access_token().await,
-        )
-        .await?;
-        download_to_file(
-            &client,
-            checker_url,
-            &checker_path,
-            session.get_access_token().await,
-        )
-        .await?;
-        download_to_file(
-            &client,
-            testlib_url,
-            &testlib_path,
-            session.get_access_token().await,
-        )
-        .await?;
-        if is_problem_interactive {
-            download_to_file(
-                &client,
-                interactor_url,
-                &interactor_path,
-                session.get_access_token().await,
-            )
-            .await?;
+pub async fn process_submission(
+    context: &Context,
+    worker: &Worker,
+    job: &Job,
+) -> Result<judge_definitions::JudgeOutput, StageError> {
+    let opts = &context.opts;
+    let submission_id = job.submission_id;
+
+    if let Some(server) = &job.server {
+        if server != &opts.server {
+            log::warn!(
+                "Job originates from {}, but judging against {}.",
+                server,
+                &opts.server
+            );
         }
-
-        // Download testcases
-        download_to_file(
-            &client,
-            testcases_url,
-            &testcases_zip_path,
-            session.get_access_token().await,
-        )
-        .await?;
-        log::info!(
-            "Compressed testcases for problem {} downloaded. Extracting...",
-            &submission.problem_slug
-        );
-
-        crate::util::unzip(&testcases_zip_path, &testcases_path).await?;
-        std::fs::remove_file(&testcases_zip_path)?;
-        log::info!("Extracted testcases.");
     }
-
-    // Start the judging process.
-    let sandboxes_count_str = opts.sandboxes.to_string();
-    let mut args: Vec<&str> = vec![
-        "--metadata",
-        metadata_path.to_str().unwrap(),
-        "--language",
-        &submission.language,
-        "--source",
-        source_path.to_str().unwrap(),
-        "--checker",
-        checker_path.to_str().unwrap(),
-        "--testcases",
-        testcases_path.to_str().unwrap(),
-        "--testlib",
-        testlib_path.to_str().unwrap(),
-        "--sandboxes",
-        &sandboxes_count_str,
-        "--checker-language",
-        &opts.checker_language,
-        "--languages-definition",
-        &opts.language_definition,
-        "--verdict",
-        verdict_path.to_str().unwrap(),
-        "--verdict-format",
-        "json",
-        "-vv",
-    ];
-
-    if let Some(socket) = &opts.socket {
-        args.push("--socket");
-        args.push(socket);
+    if job.rejudge {
+        log::info!("Rejudging submission {}.", submission_id);
     }
 
-    if is_problem_interactive {
-        args.push("--interactor");
-        args.push(interactor_path.to_str().unwrap());
+    let mut session = Session::new(&opts.server);
+    session
+        .init(&opts.username, &opts.password)
+        .await
+        .map_err(|error| StageError {
+            stage: "authenticate",
+            error,
+        })?;
+
+    let mut judging = Judging::new(context, worker, job, session);
+    let result = match run_stages(&mut judging).await {
+        Err(err) if err.error.is_system_error() => report_system_error(&mut judging, err).await,
+        result => result,
+    };
+    if let Some(lease) = judging.lease.take() {
+        lease.release(&judging.client, &mut judging.session).await;
     }
 
-    // Launch TCP listening server
-    let socket = opts.socket.clone();
-    let tcp_listener_thread = if let Some(socket) = socket {
-        let socket = socket.clone();
-        let session = session.clone();
-
-        log::debug!("Spawning TCP listener thread...");
-
-        Some(thread::spawn(move || {
-            let context = zmq::Context::new();
-            let requester = context.socket(zmq::SUB).unwrap();
-
-            requester
-                .connect(&socket)
-                .expect("Failed to connect to socket.");
-            requester
-                .set_subscribe(b"")
-                .expect("Failed to set subscription.");
-
-            let mut judged_testcases: i32 = 0;
-            let mut prev_request_instant = std::time::Instant::now();
-            // TODO use correct total_testcases
-            let total_testcases: i32 = problem.testcases.len() as i32;
-
-            let mut msg = zmq::Message::new();
-            loop {
-                requester.recv(&mut msg, 0).unwrap();
-                println!("Received message: {}", msg.as_str().unwrap());
-
-                let value: serde_json::Value = serde_json::from_str(msg.as_str().unwrap()).unwrap();
-                let event_type = value["event_type"].as_str().unwrap();
-
-                if event_type == "testcase" {
-                    // One submission received.
-                    judged_testcases += 1;
+    log::info!(
+        "Stages of submission {}: {}",
+        submission_id,
+        judging
+            .timings
+            .iter()
+            .map(|(stage, elapsed)| format!("{} {:.3}s", stage, elapsed.as_secs_f64()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    result?;
+    log::info!("Judging finished.");
 
-                    // TODO set cooldown (e.g. 1s) for status update
-                    let mut session = session.clone();
-                    let client = reqwest::Client::new();
+    Ok(judging.verdict.take().expect("The judge has not run."))
+}
 
-                    if judged_testcases < total_testcases
-                        && prev_request_instant.elapsed() > std::time::Duration::from_secs(1)
-                    {
-                        prev_request_instant = std::time::Instant::now();
+/// Run the stages of the pipeline in order, stopping at the first one that fails.
+async fn run_stages(judging: &mut Judging<'_>) -> Result<(), StageError> {
+    for stage in stage::pipeline() {
+        run_stage(judging, stage.as_ref()).await?;
+    }
 
-                        let mut rt = tokio::runtime::Runtime::new().unwrap();
-                        let local = tokio::task::LocalSet::new();
-                        local.block_on(&mut rt, async move {
-                            let _response = client
-                                .put(session.resolve(vec![
-                                    "submission/",
-                                    &format!("{}/", submission_id),
-                                    "judge/progress",
-                                ]))
-                                .bearer_auth(session.get_access_token().await)
-                                .json(&json!({
-                                    "progress": judged_testcases,
-                                    "total": total_testcases,
-                                }))
-                                .send()
-                                .await;
-                        });
-                    }
-                }
+    Ok(())
+}
 
-                if event_type == "submission" {
-                    // The judging is completed and the thread should terminate.
-                    break;
-                }
-            }
+async fn run_stage(judging: &mut Judging<'_>, stage: &dyn Stage) -> Result<(), StageError> {
+    log::debug!("Running stage {}...", stage.name());
+    let start = Instant::now();
+    let result = stage.run(judging).await;
+    judging.timings.push((stage.name(), start.elapsed()));
 
-            ()
-        }))
-    } else {
-        None
-    };
+    result.map_err(|error| StageError {
+        stage: stage.name(),
+        error,
+    })
+}
 
-    log::info!("Start judging with arguments: {}", args.join(" "));
-    let mut child = Command::new(&opts.judge)
-        .args(&args)
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .spawn()
-        .unwrap();
+/// Give the submission a System Error verdict explaining `err`, as retrying it would fail the
+/// same way.
+async fn report_system_error(judging: &mut Judging<'_>, err: StageError) -> Result<(), StageError> {
+    log::error!(
+        "Reporting System Error for submission {}: {}",
+        judging.job.submission_id,
+        err
+    );
+    judging.verdict = Some(system_error(err.error.to_string()));
 
-    child.wait()?;
-    if let Some(thread) = tcp_listener_thread {
-        thread.join().unwrap();
-    }
+    run_stage(judging, &PublishVerdict).await
+}
 
-    let verdict: judge_definitions::JudgeOutput;
-    if verdict_path.exists() {
-        verdict = serde_json::from_str(&std::fs::read_to_string(verdict_path).unwrap()).unwrap();
-    } else {
-        verdict = judge_definitions::JudgeOutput {
-            verdict: judge_definitions::verdicts::VERDICT_SE.into(),
-            compile_message: "".to_string(),
-            time: 0.,
-            memory: 0,
-            testcases: vec![],
-        };
+fn system_error(message: String) -> judge_definitions::JudgeOutput {
+    judge_definitions::JudgeOutput {
+        verdict: judge_definitions::verdicts::VERDICT_SE.into(),
+        compile_message: message,
+        time: 0.,
+        memory: 0,
+        testcases: vec![],
     }
-
-    let response = client
-        .put(session.resolve(vec!["submission/", &format!("{}/", submission_id), "judge"]))
-        .bearer_auth(session.get_access_token().await)
-        .json(&verdict)
-        .send()
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-
-    log::info!("Verdict: {}", verdict.verdict);
-    log::info!(
-        "Push to {}, response: {}",
-        session.resolve(vec!["submission/", &format!("{}/", submission_id), "judge"]),
-        response
-    );
-    log::info!("Judging finished.");
-
-    Ok(())
 }
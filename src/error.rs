@@ -0,0 +1,120 @@
+use std::fmt;
+
+/// The errors that can occur while judging a submission.
+#[derive(Debug)]
+pub enum ControllerError {
+    /// Logging in to the judge server or refreshing the access token failed.
+    Auth(String),
+    /// A request to the judge server failed.
+    Network(String, reqwest::Error),
+    /// A file could not be read or written.
+    Io(String, std::io::Error),
+    /// The problem files in the cache are corrupted or could not be stored.
+    Cache(String),
+    /// The problem metadata is malformed.
+    Metadata(String),
+    /// The submission cannot be judged on this host.
+    Unsupported(String),
+    /// The checker or the interactor of the problem could not be compiled.
+    Compile(String),
+    /// The judge exited without writing a verdict.
+    JudgeCrash(String),
+    /// The judge did not finish within the given number of seconds.
+    JudgeTimeout(u64),
+    /// The verdict written by the judge could not be parsed.
+    VerdictParse(serde_json::Error),
+    /// The verdict could not be published to the result exchange.
+    Publish(lapin::Error),
+    /// The result exchange did not confirm the verdict.
+    Unconfirmed(String),
+    /// The submission is being judged by another controller.
+    LeaseConflict(i32),
+    /// The lease on the submission could not be renewed before it expired.
+    LeaseExpired(i32),
+    /// The judging was abandoned because the controller is shutting down.
+    Aborted,
+    /// A stage was run before the stages it depends on.
+    OutOfOrder(&'static str),
+}
+
+impl ControllerError {
+    /// Wrap a failed request to the judge server, describing what was requested in `context`.
+    pub fn network(context: &str) -> impl FnOnce(reqwest::Error) -> ControllerError + '_ {
+        move |err| ControllerError::Network(context.to_string(), err)
+    }
+
+    /// Wrap a failed file operation, describing the operation in `context`.
+    pub fn io(context: &str) -> impl FnOnce(std::io::Error) -> ControllerError + '_ {
+        move |err| ControllerError::Io(context.to_string(), err)
+    }
+
+    /// Whether the error would occur again on retrying, so that the submission should rather be
+    /// given a System Error verdict.
+    pub fn is_system_error(&self) -> bool {
+        matches!(
+            self,
+            ControllerError::Metadata(_)
+                | ControllerError::Compile(_)
+                | ControllerError::JudgeCrash(_)
+                | ControllerError::JudgeTimeout(_)
+                | ControllerError::VerdictParse(_)
+        )
+    }
+}
+
+impl fmt::Display for ControllerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ControllerError::Auth(reason) => {
+                write!(
+                    f,
+                    "Failed to authenticate with the judge server: {}",
+                    reason
+                )
+            }
+            ControllerError::Network(context, err) => write!(f, "{}: {}", context, err),
+            ControllerError::Io(context, err) => write!(f, "{}: {}", context, err),
+            ControllerError::Cache(reason) => write!(f, "Problem cache error: {}", reason),
+            ControllerError::Metadata(reason) => write!(f, "Invalid problem metadata: {}", reason),
+            ControllerError::Unsupported(reason) => write!(f, "Cannot judge here: {}", reason),
+            ControllerError::Compile(reason) => write!(f, "Failed to compile: {}", reason),
+            ControllerError::JudgeCrash(reason) => write!(f, "Judge crashed: {}", reason),
+            ControllerError::JudgeTimeout(seconds) => write!(
+                f,
+                "Judging did not finish within {} seconds and has been stopped.",
+                seconds
+            ),
+            ControllerError::VerdictParse(err) => {
+                write!(f, "Failed to parse the verdict of the judge: {}", err)
+            }
+            ControllerError::Publish(err) => write!(f, "Failed to publish the verdict: {}", err),
+            ControllerError::Unconfirmed(reason) => {
+                write!(f, "The verdict was not confirmed: {}", reason)
+            }
+            ControllerError::LeaseConflict(submission_id) => write!(
+                f,
+                "Submission {} is being judged by another controller.",
+                submission_id
+            ),
+            ControllerError::LeaseExpired(submission_id) => write!(
+                f,
+                "The lease on submission {} expired as it could not be renewed.",
+                submission_id
+            ),
+            ControllerError::Aborted => write!(f, "Judging aborted due to shutdown."),
+            ControllerError::OutOfOrder(reason) => write!(f, "Stage run out of order: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ControllerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ControllerError::Network(_, err) => Some(err),
+            ControllerError::Io(_, err) => Some(err),
+            ControllerError::VerdictParse(err) => Some(err),
+            ControllerError::Publish(err) => Some(err),
+            _ => None,
+        }
+    }
+}
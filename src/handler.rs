@@ -0,0 +1,58 @@
+use crate::controller::{self, Context};
+use crate::error::ControllerError;
+use crate::source::{Outcome, ReceivedJob};
+use crate::worker::Worker;
+
+/// Judge the submission of `received` on `worker` and settle the job with its source afterwards.
+pub async fn handle_job(context: Context, worker: Worker, received: ReceivedJob) {
+    let ReceivedJob { job, ticket } = received;
+    let submission_id = job.submission_id;
+    log::info!(
+        "Processing submission {} on worker {}.",
+        submission_id,
+        worker.id
+    );
+
+    let registry = context.registry.clone();
+    registry.start(submission_id);
+
+    // Run the judging in a separate task so that a panic only fails this submission.
+    let result =
+        tokio::spawn(async move { controller::process_submission(&context, &worker, &job).await })
+            .await;
+    registry.finish(submission_id);
+
+    let outcome = match result {
+        Ok(Ok(_)) => {
+            log::info!("Finished processing submission {}.", submission_id);
+            Outcome::Done
+        }
+        Ok(Err(err)) => match &err.error {
+            ControllerError::Aborted => Outcome::Aborted,
+            ControllerError::LeaseConflict(_) => {
+                log::warn!("Skipping submission {}: {}", submission_id, err);
+                Outcome::Skipped
+            }
+            ControllerError::Unsupported(_) => {
+                let reason = format!("Rejecting submission {}: {}", submission_id, err);
+                log::warn!("{}", reason);
+                Outcome::Rejected(reason)
+            }
+            _ => {
+                let reason = format!("Failed to process submission {}: {}", submission_id, err);
+                log::error!("{}", reason);
+                Outcome::Failed(reason)
+            }
+        },
+        Err(err) => {
+            let reason = format!(
+                "Processing of submission {} panicked: {}",
+                submission_id, err
+            );
+            log::error!("{}", reason);
+            Outcome::Failed(reason)
+        }
+    };
+
+    ticket.settle(outcome).await;
+}
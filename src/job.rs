@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+
+/// The reason a submission is being judged.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// A submission made during a live contest.
+    Contest,
+    /// A submission made outside of contests.
+    Practice,
+    /// A judging requested by the problem setter to validate the problem.
+    Validation,
+}
+
+impl Default for JobKind {
+    fn default() -> JobKind {
+        JobKind::Practice
+    }
+}
+
+/// A request to judge a submission.
+///
+/// Jobs are sent as JSON objects, e.g.
+/// `{"submission_id": 1234, "kind": "contest", "priority": 5}`. A plain integer body is still
+/// accepted and treated as a practice job for that submission ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub submission_id: i32,
+    #[serde(default)]
+    pub kind: JobKind,
+    /// The priority of the job. Jobs with higher priority are judged first if the queue is
+    /// declared with `--max-priority`. The AMQP server only orders messages by their `priority`
+    /// property, which publishers must set to this priority as well. The priority in the body is
+    /// only applied by the controller when it requeues a failed job.
+    #[serde(default)]
+    pub priority: u8,
+    #[serde(default)]
+    pub rejudge: bool,
+    /// The zero-based indices of the testcases to judge. All testcases are judged if absent.
+    #[serde(default)]
+    pub testcases: Option<Vec<usize>>,
+    /// The memory limit in MB of the problem, if known by the server.
+    #[serde(default)]
+    pub memory_limit: Option<u64>,
+    /// The server the job originates from.
+    #[serde(default)]
+    pub server: Option<String>,
+}
+
+impl Job {
+    pub fn new(submission_id: i32) -> Job {
+        Job {
+            submission_id,
+            kind: JobKind::default(),
+            priority: 0,
+            rejudge: false,
+            testcases: None,
+            memory_limit: None,
+            server: None,
+        }
+    }
+
+    /// Parse a job from a message body, which is either a JSON job or a plain submission ID.
+    pub fn parse(payload: &[u8]) -> Result<Job, String> {
+        let payload = String::from_utf8_lossy(payload);
+        let payload = payload.trim();
+
+        if let Ok(submission_id) = payload.parse::<i32>() {
+            return Ok(Job::new(submission_id));
+        }
+
+        serde_json::from_str(payload)
+            .map_err(|err| format!("Failed to parse job from {:?}: {}", payload, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_submission_ids() {
+        for payload in &["1234", " 1234\n"] {
+            let job = Job::parse(payload.as_bytes()).unwrap();
+
+            assert_eq!(job.submission_id, 1234);
+            assert_eq!(job.kind, JobKind::Practice);
+            assert_eq!(job.priority, 0);
+            assert!(!job.rejudge);
+        }
+    }
+
+    #[test]
+    fn parses_minimal_json_jobs_with_defaults() {
+        let job = Job::parse(br#"{"submission_id": 1234}"#).unwrap();
+
+        assert_eq!(job.submission_id, 1234);
+        assert_eq!(job.kind, JobKind::Practice);
+        assert_eq!(job.priority, 0);
+        assert!(!job.rejudge);
+        assert_eq!(job.testcases, None);
+        assert_eq!(job.memory_limit, None);
+        assert_eq!(job.server, None);
+    }
+
+    #[test]
+    fn parses_full_json_jobs() {
+        let job = Job::parse(
+            br#"{
+                "submission_id": 1234,
+                "kind": "contest",
+                "priority": 5,
+                "rejudge": true,
+                "testcases": [0, 2],
+                "memory_limit": 256,
+                "server": "https://judge.example.com/"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(job.submission_id, 1234);
+        assert_eq!(job.kind, JobKind::Contest);
+        assert_eq!(job.priority, 5);
+        assert!(job.rejudge);
+        assert_eq!(job.testcases, Some(vec![0, 2]));
+        assert_eq!(job.memory_limit, Some(256));
+        assert_eq!(job.server.as_deref(), Some("https://judge.example.com/"));
+    }
+
+    #[test]
+    fn rejects_malformed_payloads() {
+        for payload in &[
+            "",
+            "12a4",
+            r#"{"kind": "contest"}"#,
+            r#"{"submission_id": "#,
+        ] {
+            assert!(Job::parse(payload.as_bytes()).is_err(), "{:?}", payload);
+        }
+    }
+}
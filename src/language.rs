@@ -0,0 +1,188 @@
+use crate::capabilities::Capabilities;
+use crate::error::ControllerError;
+use reqwest::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_TYPE};
+use std::path::{Path, PathBuf};
+
+/// The extension given to a downloaded program whose language cannot be told from the response.
+pub const DEFAULT_EXTENSION: &str = "cpp";
+
+/// The language codes a program with the given extension may be written in, most preferred
+/// first. C++ programs are compiled with the checker language passed on the command line.
+const EXTENSION_LANGUAGES: &[(&str, &[&str])] = &[
+    ("c", &["c", "c11", "c99"]),
+    ("py", &["python3", "python", "py", "pypy3"]),
+    ("rs", &["rust", "rs"]),
+    ("java", &["java"]),
+    ("go", &["go", "golang"]),
+    ("js", &["javascript", "js", "nodejs", "node"]),
+    ("pas", &["pascal", "pas"]),
+    ("hs", &["haskell", "hs"]),
+    ("kt", &["kotlin", "kt"]),
+];
+
+const CPP_EXTENSIONS: &[&str] = &["cpp", "cc", "cxx", "c++"];
+
+/// The file extensions implied by the content types a program may be served with.
+const CONTENT_TYPE_EXTENSIONS: &[(&str, &str)] = &[
+    ("text/x-c++src", "cpp"),
+    ("text/x-c++", "cpp"),
+    ("text/x-csrc", "c"),
+    ("text/x-c", "c"),
+    ("text/x-python", "py"),
+    ("text/x-script.python", "py"),
+    ("text/x-rust", "rs"),
+    ("text/rust", "rs"),
+    ("text/x-java", "java"),
+    ("text/x-java-source", "java"),
+    ("text/x-go", "go"),
+    ("text/javascript", "js"),
+    ("application/javascript", "js"),
+    ("text/x-pascal", "pas"),
+    ("text/x-haskell", "hs"),
+    ("text/x-kotlin", "kt"),
+];
+
+/// Tell the extension of a downloaded program from the file name in the `Content-Disposition`
+/// header, or else from the `Content-Type` header.
+pub fn extension_from_headers(headers: &HeaderMap) -> Option<String> {
+    let from_file_name = headers
+        .get(CONTENT_DISPOSITION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value
+                .split(';')
+                .map(str::trim)
+                .find(|param| param.starts_with("filename="))
+                .map(|param| param["filename=".len()..].trim_matches('"').to_string())
+        })
+        .and_then(|file_name| {
+            Path::new(&file_name)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .map(str::to_lowercase)
+        });
+    let from_content_type = || {
+        headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.split(';').next().unwrap_or("").trim().to_lowercase())
+            .and_then(|content_type| {
+                CONTENT_TYPE_EXTENSIONS
+                    .iter()
+                    .find(|(known, _)| *known == content_type)
+                    .map(|(_, extension)| extension.to_string())
+            })
+    };
+
+    from_file_name
+        .filter(|extension| {
+            !extension.is_empty()
+                && extension
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+')
+        })
+        .or_else(from_content_type)
+}
+
+/// Find the program named `name` with any extension in `folder`.
+pub fn find_program(folder: &Path, name: &str) -> Option<PathBuf> {
+    std::fs::read_dir(folder)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.is_file() && path.file_stem().and_then(|stem| stem.to_str()) == Some(name)
+        })
+}
+
+/// Determine the language of the program at `path`. The language given in the problem metadata
+/// takes precedence over the one told by the extension of the program. C++ programs and programs
+/// of unknown language are compiled with `default_language`.
+pub fn resolve(
+    configured: Option<&str>,
+    path: &Path,
+    capabilities: &Capabilities,
+    default_language: &str,
+) -> Result<String, ControllerError> {
+    let supported = |language: &str| capabilities.languages.iter().any(|code| code == language);
+
+    if let Some(language) = configured {
+        if !supported(language) {
+            return Err(ControllerError::Metadata(format!(
+                "Language {} of {} is not supported.",
+                language,
+                path.display()
+            )));
+        }
+        return Ok(language.to_string());
+    }
+
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    if extension.is_empty() || CPP_EXTENSIONS.contains(&extension.as_str()) {
+        return Ok(default_language.to_string());
+    }
+
+    let candidates = EXTENSION_LANGUAGES
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map_or(&[][..], |(_, candidates)| *candidates);
+    std::iter::once(extension.as_str())
+        .chain(candidates.iter().cloned())
+        .find(|language| supported(language))
+        .map(String::from)
+        .ok_or_else(|| {
+            ControllerError::Metadata(format!("No supported language for {}.", path.display()))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            languages: vec!["cpp".to_string(), "python3".to_string()],
+            interactive: true,
+            max_memory: None,
+        }
+    }
+
+    #[test]
+    fn resolves_the_configured_language_before_the_extension() {
+        let language = resolve(
+            Some("python3"),
+            Path::new("checker.cpp"),
+            &capabilities(),
+            "cpp",
+        );
+
+        assert_eq!(language.unwrap(), "python3");
+    }
+
+    #[test]
+    fn resolves_the_language_from_the_extension() {
+        let resolve = |path| resolve(None, Path::new(path), &capabilities(), "cpp").unwrap();
+
+        assert_eq!(resolve("checker.py"), "python3");
+        assert_eq!(resolve("checker.cc"), "cpp");
+        assert_eq!(resolve("checker"), "cpp");
+    }
+
+    #[test]
+    fn unsupported_languages_are_system_errors() {
+        let configured = resolve(
+            Some("rust"),
+            Path::new("checker.rs"),
+            &capabilities(),
+            "cpp",
+        );
+        let detected = resolve(None, Path::new("checker.rs"), &capabilities(), "cpp");
+
+        assert!(configured.unwrap_err().is_system_error());
+        assert!(detected.unwrap_err().is_system_error());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::api::LeaseRequest;
+use crate::error::ControllerError;
+use crate::session::Session;
+use crate::shutdown::{self, Trigger, TriggerHandle};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A lease on a submission, granting this controller the exclusive right to judge it. The lease
+/// is renewed in the background until it is released or dropped.
+pub struct Lease {
+    submission_id: i32,
+    request: LeaseRequest,
+    lost: Trigger,
+    /// Whether the lease has been lost by expiring rather than being taken over.
+    expired: Arc<AtomicBool>,
+    released: TriggerHandle,
+}
+
+impl Lease {
+    /// Acquire the lease on the submission for `duration` seconds. Fails with
+    /// `ControllerError::LeaseConflict` if another controller holds the lease.
+    pub async fn acquire(
+        client: &reqwest::Client,
+        session: &mut Session,
+        owner: &str,
+        submission_id: i32,
+        duration: u64,
+    ) -> Result<Lease, ControllerError> {
+        let request = LeaseRequest {
+            controller: String::from(owner),
+            duration,
+        };
+
+        let context = "Failed to acquire the lease";
+        let response = client
+            .post(lease_url(session, submission_id))
+            .bearer_auth(session.get_access_token().await?)
+            .json(&request)
+            .send()
+            .await
+            .map_err(ControllerError::network(context))?;
+        if response.status() == reqwest::StatusCode::CONFLICT {
+            return Err(ControllerError::LeaseConflict(submission_id));
+        }
+        response
+            .error_for_status()
+            .map_err(ControllerError::network(context))?;
+        log::info!("Acquired lease on submission {}.", submission_id);
+
+        let (lost_handle, lost) = shutdown::trigger();
+        let (released, mut released_trigger) = shutdown::trigger();
+        let expired = Arc::new(AtomicBool::new(false));
+
+        // Renew the lease well before it expires, leaving room for a failed renewal. The lease
+        // is given up once it would expire before the next renewal, as another controller may
+        // take it over from then on.
+        let lease_duration = Duration::from_secs(duration);
+        let renew_interval = Duration::from_secs(std::cmp::max(duration / 3, 1));
+        let mut renewed_at = Instant::now();
+        let client = client.clone();
+        let mut session = session.clone();
+        let renewal = LeaseRequest {
+            controller: request.controller.clone(),
+            duration,
+        };
+        let renewal_expired = expired.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::delay_for(renew_interval) => {}
+                    _ = released_trigger.fired() => return,
+                }
+
+                let result = match session.get_access_token().await {
+                    Ok(access_token) => {
+                        let access_token = access_token.to_string();
+                        client
+                            .put(lease_url(&session, submission_id))
+                            .bearer_auth(access_token)
+                            .json(&renewal)
+                            .send()
+                            .await
+                            .and_then(|response| response.error_for_status())
+                            .map_err(ControllerError::network("Request failed"))
+                    }
+                    Err(err) => Err(err),
+                };
+                match result {
+                    Ok(_) => {
+                        renewed_at = Instant::now();
+                        log::debug!("Renewed lease on submission {}.", submission_id);
+                    }
+                    Err(ControllerError::Network(_, err))
+                        if err.status() == Some(reqwest::StatusCode::CONFLICT) =>
+                    {
+                        log::error!(
+                            "Lease on submission {} has been taken over by another controller.",
+                            submission_id
+                        );
+                        lost_handle.fire();
+                        return;
+                    }
+                    Err(err) if renewed_at.elapsed() + renew_interval >= lease_duration => {
+                        log::error!(
+                            "Failed to renew lease on submission {} before it expires: {}",
+                            submission_id,
+                            err
+                        );
+                        renewal_expired.store(true, Ordering::SeqCst);
+                        lost_handle.fire();
+                        return;
+                    }
+                    // The lease is still valid until it expires, so keep trying.
+                    Err(err) => log::warn!(
+                        "Failed to renew lease on submission {}: {}",
+                        submission_id,
+                        err
+                    ),
+                }
+            }
+        });
+
+        Ok(Lease {
+            submission_id,
+            request,
+            lost,
+            expired,
+            released,
+        })
+    }
+
+    /// Fired when the lease has been taken over by another controller, or is about to expire as
+    /// it could not be renewed.
+    pub fn lost(&self) -> &Trigger {
+        &self.lost
+    }
+
+    /// The error to fail the judging with once the lease has been lost. A submission whose lease
+    /// has expired is not known to be judged by another controller, so it is retried.
+    pub fn lost_error(&self) -> ControllerError {
+        if self.expired.load(Ordering::SeqCst) {
+            ControllerError::LeaseExpired(self.submission_id)
+        } else {
+            ControllerError::LeaseConflict(self.submission_id)
+        }
+    }
+
+    /// Release the lease, so that the submission can be picked up by another controller right
+    /// away.
+    pub async fn release(self, client: &reqwest::Client, session: &mut Session) {
+        self.released.fire();
+        if self.lost.is_fired() {
+            return;
+        }
+
+        let access_token = match session.get_access_token().await {
+            Ok(access_token) => access_token.to_string(),
+            Err(err) => {
+                log::warn!(
+                    "Failed to release lease on submission {}: {}",
+                    self.submission_id,
+                    err
+                );
+                return;
+            }
+        };
+        let result = client
+            .delete(lease_url(session, self.submission_id))
+            .bearer_auth(access_token)
+            .json(&self.request)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => log::info!("Released lease on submission {}.", self.submission_id),
+            Err(err) => log::warn!(
+                "Failed to release lease on submission {}: {}",
+                self.submission_id,
+                err
+            ),
+        }
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        // Stop renewing, so that the lease expires on its own.
+        self.released.fire();
+    }
+}
+
+fn lease_url(session: &Session, submission_id: i32) -> url::Url {
+    session.resolve(vec!["submission/", &format!("{}/", submission_id), "lease"])
+}
@@ -1,15 +1,43 @@
+mod amqp;
 mod api;
+mod cache;
+mod capabilities;
 mod cli;
+mod command;
+mod compile;
+mod consumer;
 mod controller;
+mod error;
+mod handler;
+mod job;
+mod language;
+mod lease;
 mod logger;
 mod net;
 mod precheck;
+mod problem;
+mod process;
+mod publisher;
+mod registry;
+mod scoring;
 mod session;
+mod shutdown;
+mod source;
+mod stage;
+mod state;
 mod util;
+mod worker;
 
+use cache::ProblemLocks;
+use capabilities::Capabilities;
 use clap::derive::Clap;
-use cli::Opts;
-use lapin::{options::*, types::FieldTable, Connection, ConnectionProperties};
+use cli::{Command, Opts};
+use compile::Compilers;
+use controller::Context;
+use process::JudgeFlags;
+use registry::Registry;
+use std::sync::Arc;
+use worker::WorkerPool;
 
 #[tokio::main]
 async fn main() -> () {
@@ -17,66 +45,77 @@ async fn main() -> () {
 
     logger::init_logger(&opts);
     cli::debug_opts(&opts);
+    precheck::check_server(&opts);
+    precheck::check_workers(&opts);
+    if opts.command.is_none() {
+        precheck::check_source(&opts);
+    }
+    precheck::check_results(&opts);
     precheck::create_folders(&opts);
 
-    loop {
-        let conn = Connection::connect(&opts.amqp_url, ConnectionProperties::default())
-            .await
-            .expect("Connection error.");
-
-        log::info!("Connected to AMQP server.");
+    let opts = Arc::new(opts);
 
-        let channel = conn
-            .create_channel()
-            .await
-            .expect("Failed to create channel.");
-        channel
-            .basic_qos(1, BasicQosOptions::default())
-            .await
-            .expect("Failed to set prefetch count.");
-        let _queue = channel.queue_declare(
-            "JUDGE_QUEUE",
-            QueueDeclareOptions {
-                durable: true,
-                ..QueueDeclareOptions::default()
-            },
-            FieldTable::default(),
-        );
+    if let Some(Command::Judge(judge)) = &opts.command {
+        let submission_ids = match command::parse_submission_ids(&judge.submissions) {
+            Ok(submission_ids) => submission_ids,
+            Err(err) => {
+                log::error!("{}", err);
+                std::process::exit(2);
+            }
+        };
 
-        log::info!("Starting consumer...");
-        let consumer = channel
-            .basic_consume(
-                "JUDGE_QUEUE",
-                "judge-controller",
-                BasicConsumeOptions::default(),
-                FieldTable::default(),
-            )
-            .await
-            .expect("Creating consumer failed.");
+        let success = command::judge_submissions(opts.clone(), &submission_ids).await;
+        std::process::exit(if success { 0 } else { 1 });
+    }
+    let locks = ProblemLocks::new();
+    let capabilities = Arc::new(Capabilities::detect(&opts));
+    log::info!("Capabilities: {:?}", capabilities);
+    precheck::check_capabilities(&opts, &capabilities);
+    let compilers = Arc::new(Compilers::detect(&opts));
+    let judge_flags = Arc::new(JudgeFlags::detect(&opts.judge));
+    log::debug!("Judge flags: {:?}", judge_flags);
+    precheck::check_judge(&opts, &judge_flags);
+    let pool = WorkerPool::new(&opts);
+    let source = source::create(opts.clone(), &capabilities);
 
-        for delivery in consumer {
-            if let Ok(delivery) = delivery {
-                let submission_id = String::from_utf8_lossy(&delivery.data)
-                    .parse::<i32>()
-                    .expect("Failed to parse submission ID.");
-                log::info!("Accepted request to process submission {}.", submission_id);
+    let stop = shutdown::listen_for_signals();
+    let (abort_handle, abort) = shutdown::trigger();
 
-                // Acknowledge the delivery to prevent it the task from being
-                // processed by multiple controller processes.
-                channel
-                    .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
-                    .await
-                    .expect("Basic ACK failed.");
+    let registry = Arc::new(Registry::new(&opts));
+    if !opts.no_register {
+        registry::spawn_heartbeat(
+            opts.clone(),
+            capabilities.clone(),
+            registry.clone(),
+            stop.clone(),
+        );
+    }
 
-                controller::process_submission(&opts, submission_id)
-                    .await
-                    .unwrap();
+    let context = Context {
+        opts: opts.clone(),
+        locks,
+        capabilities,
+        compilers,
+        judge_flags,
+        registry,
+        publisher: None,
+        abort,
+    };
+    let drained = consumer::run(
+        context,
+        source,
+        pool,
+        stop,
+        abort_handle,
+        handler::handle_job,
+    )
+    .await;
 
-                log::info!(
-                    "Finished processing submission {}. Acknowledging.",
-                    submission_id
-                );
-            }
-        }
+    if drained {
+        log::info!("All submissions finished. Exiting.");
+        std::process::exit(0);
+    } else {
+        log::warn!("Exiting after requeueing aborted submissions.");
+        std::process::exit(1);
     }
 }
@@ -1,28 +1,43 @@
+use crate::error::ControllerError;
 use futures_util::StreamExt;
 use std::io::Write;
 use url::Url;
 
 /// Using the reqwest client `client` provided, download file from `url` to `path` using the passed
-/// `access_token`.
+/// `access_token`. The headers of the response are returned. If `etag` is given, it is sent in
+/// `If-None-Match` and `None` is returned without writing the file if the server reports that the
+/// file is unchanged.
 pub async fn download_to_file<'a>(
     client: &reqwest::Client,
     url: Url,
     path: &'a std::path::Path,
     access_token: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut stream = client
-        .get(url)
-        .bearer_auth(access_token)
+    etag: Option<&str>,
+) -> Result<Option<reqwest::header::HeaderMap>, ControllerError> {
+    let download_context = format!("Failed to download {}", url);
+    let mut request = client.get(url).bearer_auth(access_token);
+    if let Some(etag) = etag {
+        request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+    }
+    let response = request
         .send()
         .await
-        .unwrap()
-        .bytes_stream();
+        .and_then(|response| response.error_for_status())
+        .map_err(ControllerError::network(&download_context))?;
+    if etag.is_some() && response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    let headers = response.headers().clone();
+    let mut stream = response.bytes_stream();
 
-    let mut file = std::fs::File::create(path).unwrap();
-    while let Some(Ok(item)) = stream.next().await {
-        file.write(&item)?;
+    let context = format!("Failed to write {}", path.display());
+    let mut file = std::fs::File::create(path).map_err(ControllerError::io(&context))?;
+    while let Some(item) = stream.next().await {
+        let item = item.map_err(ControllerError::network(&download_context))?;
+        file.write_all(&item)
+            .map_err(ControllerError::io(&context))?;
     }
-    file.flush()?;
+    file.flush().map_err(ControllerError::io(&context))?;
 
-    Ok(())
+    Ok(Some(headers))
 }
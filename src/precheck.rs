@@ -1,9 +1,68 @@
+use crate::capabilities::Capabilities;
 use crate::cli::Opts;
+use crate::process::{JudgeFlags, SANDBOX_OFFSET_FLAG};
+use crate::worker::Worker;
 use std::{fs, path::Path};
 
 pub fn create_folders(opts: &Opts) {
     fs::create_dir_all(Path::new(&opts.folder)).unwrap();
     fs::create_dir_all(Path::new(&opts.temp)).unwrap();
 
+    for id in 0..opts.workers {
+        fs::create_dir_all(Worker::new(opts, id).temp).unwrap();
+    }
+
     log::info!("Created folders.");
 }
+
+pub fn check_server(opts: &Opts) {
+    if let Err(err) = url::Url::parse(&opts.server) {
+        panic!("Invalid server URL {}: {}", opts.server, err);
+    }
+}
+
+pub fn check_workers(opts: &Opts) {
+    if opts.workers < 1 {
+        panic!("At least one worker is required.");
+    }
+    if opts.sandboxes < opts.workers {
+        panic!(
+            "Cannot split {} sandboxes between {} workers.",
+            opts.sandboxes, opts.workers
+        );
+    }
+}
+
+pub fn check_judge(opts: &Opts, judge_flags: &JudgeFlags) {
+    if opts.workers > 1 && !judge_flags.supports(SANDBOX_OFFSET_FLAG) {
+        panic!(
+            "The judge does not support {}, which is required to split the sandboxes between \
+             workers. Run with a single worker or upgrade the judge.",
+            SANDBOX_OFFSET_FLAG
+        );
+    }
+}
+
+pub fn check_source(opts: &Opts) {
+    match opts.source.as_str() {
+        "amqp" if opts.amqp_url.is_none() => panic!("The amqp source requires --amqp-url."),
+        "spool" if opts.spool.is_none() => panic!("The spool source requires --spool."),
+        "amqp" | "http" | "spool" | "stdin" => {}
+        source => panic!("Unknown job source {}.", source),
+    }
+    if opts.result_exchange.is_some() && opts.source != "amqp" {
+        panic!("Publishing results requires the amqp source.");
+    }
+}
+
+pub fn check_results(opts: &Opts) {
+    if opts.disable_http_results && opts.result_exchange.is_none() {
+        panic!("A result exchange is required when HTTP results are disabled.");
+    }
+}
+
+pub fn check_capabilities(opts: &Opts, capabilities: &Capabilities) {
+    if opts.capability_routing && capabilities.languages.is_empty() {
+        panic!("No languages found in the language definition to route by.");
+    }
+}
@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProblemMetadata {
+    #[serde(rename = "type")]
+    pub problem_type: ProblemType,
+    pub last_update: String,
+    /// The version of the problem resources, which changes whenever they are updated. The last
+    /// update time is used as the version if not given.
+    #[serde(default)]
+    pub version: Option<String>,
+    /// The time limit per testcase in seconds.
+    #[serde(default)]
+    pub time_limit: Option<f64>,
+    /// The memory limit per testcase in MB.
+    #[serde(default)]
+    pub memory_limit: Option<u64>,
+    pub testcases: Vec<Testcase>,
+    /// The groups the testcases are split into, if the problem has subtasks.
+    #[serde(default)]
+    pub groups: Vec<TestcaseGroup>,
+    #[serde(default)]
+    pub scoring: Scoring,
+    #[serde(default)]
+    pub checker: ProgramSettings,
+    #[serde(default)]
+    pub interactor: ProgramSettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ProblemType {
+    Standard,
+    Interactive,
+    /// A problem type unknown to the controller, judged like a standard problem.
+    Other(String),
+}
+
+/// A testcase of the problem. Older problems list each testcase as a bare value such as the name
+/// of its files instead of a mapping, which is read as the name of the testcase.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "TestcaseEntry")]
+pub struct Testcase {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub input: Option<String>,
+    #[serde(default)]
+    pub output: Option<String>,
+    /// The name of the group the testcase belongs to.
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+/// The shapes a testcase may be listed in.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TestcaseEntry {
+    Mapping {
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        input: Option<String>,
+        #[serde(default)]
+        output: Option<String>,
+        #[serde(default)]
+        group: Option<String>,
+    },
+    Legacy(serde_yaml::Value),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TestcaseGroup {
+    pub name: String,
+    /// The score awarded for the group.
+    #[serde(default)]
+    pub score: f64,
+    #[serde(default)]
+    pub policy: GroupPolicy,
+}
+
+/// How the results of the testcases in a group are combined into the score of the group.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupPolicy {
+    /// Each testcase is awarded an equal share of the score of the group.
+    Sum,
+    /// The group is only awarded if every testcase is accepted.
+    AllOrNothing,
+}
+
+/// How the score of a submission is computed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scoring {
+    /// The submission is either accepted or not.
+    Binary,
+    /// The submission is awarded the scores of the groups it passes.
+    Partial,
+}
+
+/// The settings of a program supplied with the problem, i.e. the checker or the interactor.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProgramSettings {
+    /// The language the program is written in. It is detected from the downloaded file if not
+    /// given.
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+impl ProblemMetadata {
+    pub fn is_interactive(&self) -> bool {
+        self.problem_type == ProblemType::Interactive
+    }
+
+    /// The version the cached resources of the problem are checked against.
+    pub fn resource_version(&self) -> &str {
+        self.version.as_deref().unwrap_or(&self.last_update)
+    }
+
+    /// Check that the metadata is consistent, so that a broken problem is reported before the
+    /// judging begins.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(time_limit) = self.time_limit {
+            if time_limit.is_nan() || time_limit <= 0. {
+                return Err(format!("Invalid time limit {}.", time_limit));
+            }
+        }
+        if self.memory_limit == Some(0) {
+            return Err("Invalid memory limit 0.".to_string());
+        }
+
+        let mut group_names = HashSet::new();
+        for group in &self.groups {
+            if !group_names.insert(group.name.as_str()) {
+                return Err(format!("Duplicate testcase group {}.", group.name));
+            }
+            if group.score.is_nan() || group.score < 0. {
+                return Err(format!(
+                    "Invalid score {} of testcase group {}.",
+                    group.score, group.name
+                ));
+            }
+        }
+        for (index, testcase) in self.testcases.iter().enumerate() {
+            match &testcase.group {
+                Some(group) if !group_names.contains(group.as_str()) => {
+                    return Err(format!(
+                        "Testcase {} belongs to unknown group {}.",
+                        index, group
+                    ));
+                }
+                None if !self.groups.is_empty() => {
+                    return Err(format!("Testcase {} belongs to no group.", index));
+                }
+                _ => {}
+            }
+        }
+        if self.scoring == Scoring::Partial && self.groups.is_empty() {
+            return Err("Partial scoring requires testcase groups.".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+impl From<String> for ProblemType {
+    fn from(problem_type: String) -> ProblemType {
+        match problem_type.as_str() {
+            "standard" => ProblemType::Standard,
+            "interactive" => ProblemType::Interactive,
+            _ => ProblemType::Other(problem_type),
+        }
+    }
+}
+
+impl From<ProblemType> for String {
+    fn from(problem_type: ProblemType) -> String {
+        match problem_type {
+            ProblemType::Standard => "standard".to_string(),
+            ProblemType::Interactive => "interactive".to_string(),
+            ProblemType::Other(problem_type) => problem_type,
+        }
+    }
+}
+
+impl From<TestcaseEntry> for Testcase {
+    fn from(entry: TestcaseEntry) -> Testcase {
+        match entry {
+            TestcaseEntry::Mapping {
+                name,
+                input,
+                output,
+                group,
+            } => Testcase {
+                name,
+                input,
+                output,
+                group,
+            },
+            TestcaseEntry::Legacy(value) => Testcase {
+                name: match value {
+                    serde_yaml::Value::String(name) => Some(name),
+                    serde_yaml::Value::Number(name) => Some(name.to_string()),
+                    _ => None,
+                },
+                input: None,
+                output: None,
+                group: None,
+            },
+        }
+    }
+}
+
+impl Default for GroupPolicy {
+    fn default() -> GroupPolicy {
+        GroupPolicy::AllOrNothing
+    }
+}
+
+impl Default for Scoring {
+    fn default() -> Scoring {
+        Scoring::Binary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(testcases: &str) -> ProblemMetadata {
+        serde_yaml::from_str(&format!(
+            "type: standard\nlast_update: 2020-01-01T00:00:00Z\ntestcases: {}\n",
+            testcases
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn reads_testcases_listed_as_mappings() {
+        let problem = parse("[{ name: a, input: a.in, output: a.out, group: g }]");
+
+        let testcase = &problem.testcases[0];
+        assert_eq!(testcase.name.as_deref(), Some("a"));
+        assert_eq!(testcase.input.as_deref(), Some("a.in"));
+        assert_eq!(testcase.output.as_deref(), Some("a.out"));
+        assert_eq!(testcase.group.as_deref(), Some("g"));
+    }
+
+    #[test]
+    fn reads_testcases_listed_as_bare_values() {
+        let problem = parse("[sample, 1, [a.in, a.out]]");
+
+        let names: Vec<_> = problem
+            .testcases
+            .iter()
+            .map(|testcase| testcase.name.as_deref())
+            .collect();
+        assert_eq!(names, vec![Some("sample"), Some("1"), None]);
+        assert!(problem
+            .testcases
+            .iter()
+            .all(|testcase| testcase.group.is_none()));
+        assert_eq!(problem.validate(), Ok(()));
+    }
+
+    #[test]
+    fn accepts_problems_without_testcases() {
+        let problem = parse("[]");
+
+        assert!(problem.testcases.is_empty());
+        assert_eq!(problem.validate(), Ok(()));
+    }
+}
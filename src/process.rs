@@ -0,0 +1,153 @@
+use crate::shutdown::Trigger;
+use std::collections::HashSet;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+/// The interval at which the judge process is polled for exit.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The time given to the judge process to exit after SIGTERM before it is killed.
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// The flag the judge is told the first sandbox of a worker with, if more than one worker shares
+/// the sandboxes.
+pub const SANDBOX_OFFSET_FLAG: &str = "--sandbox-offset";
+
+/// The flags the judge is passed the prebuilt executables of the checker and the interactor with.
+/// Judges without them compile the programs themselves.
+pub const CHECKER_EXECUTABLE_FLAG: &str = "--checker-executable";
+pub const INTERACTOR_EXECUTABLE_FLAG: &str = "--interactor-executable";
+
+/// The flag the judge is told the language of the interactor with. Judges without it compile the
+/// interactor in the language of the checker.
+pub const INTERACTOR_LANGUAGE_FLAG: &str = "--interactor-language";
+
+/// How the judge process has ended.
+pub enum JudgeExit {
+    Exited(ExitStatus),
+    /// The judge was killed because an abort trigger was fired.
+    Aborted,
+    /// The judge was killed because it did not exit within the timeout.
+    TimedOut,
+}
+
+/// The judge child process. It is started in its own process group, so that it can be killed
+/// together with every process it has spawned.
+pub struct JudgeProcess {
+    child: Child,
+}
+
+impl JudgeProcess {
+    pub fn spawn(program: &str, args: &[&str]) -> io::Result<JudgeProcess> {
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit());
+
+        // Also keeps signals sent to the controller's process group (e.g. Ctrl-C) away from the
+        // judge, so that the controller decides when to stop it.
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setpgid(0, 0) == 0 {
+                    Ok(())
+                } else {
+                    Err(io::Error::last_os_error())
+                }
+            });
+        }
+
+        Ok(JudgeProcess {
+            child: command.spawn()?,
+        })
+    }
+
+    /// Wait for the judge to exit. The judge is killed if any of `aborts` is fired first, or if it
+    /// is still running after `timeout`.
+    pub async fn wait(
+        &mut self,
+        aborts: &[&Trigger],
+        timeout: Option<Duration>,
+    ) -> io::Result<JudgeExit> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                return Ok(JudgeExit::Exited(status));
+            }
+            if aborts.iter().any(|abort| abort.is_fired()) {
+                self.kill().await?;
+                return Ok(JudgeExit::Aborted);
+            }
+            if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                log::error!("Judge process {} timed out.", self.child.id());
+                self.kill().await?;
+                return Ok(JudgeExit::TimedOut);
+            }
+            tokio::time::delay_for(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Send SIGTERM to the process group of the judge, and SIGKILL if it does not exit within
+    /// the grace period.
+    pub async fn kill(&mut self) -> io::Result<ExitStatus> {
+        log::warn!("Terminating judge process {}...", self.child.id());
+        self.signal(libc::SIGTERM);
+
+        let deadline = Instant::now() + KILL_GRACE_PERIOD;
+        while Instant::now() < deadline {
+            if let Some(status) = self.child.try_wait()? {
+                // Make sure no process spawned by the judge outlives it.
+                self.signal(libc::SIGKILL);
+                return Ok(status);
+            }
+            tokio::time::delay_for(POLL_INTERVAL).await;
+        }
+
+        log::warn!("Judge process {} did not exit. Killing...", self.child.id());
+        self.signal(libc::SIGKILL);
+        self.child.wait()
+    }
+
+    fn signal(&self, signal: libc::c_int) {
+        unsafe {
+            libc::kill(-(self.child.id() as libc::pid_t), signal);
+        }
+    }
+}
+
+/// The long flags the judge accepts, as listed in its help. Flags which not every version of the
+/// judge supports are only passed if the judge lists them.
+#[derive(Debug, Clone, Default)]
+pub struct JudgeFlags {
+    flags: HashSet<String>,
+}
+
+impl JudgeFlags {
+    pub fn detect(judge: &str) -> JudgeFlags {
+        let output = Command::new(judge)
+            .arg("--help")
+            .output()
+            .expect("Failed to run judge.");
+        // The help is listed even if the judge exits with an error, e.g. as it lacks arguments.
+        let help =
+            String::from_utf8_lossy(&output.stdout) + String::from_utf8_lossy(&output.stderr);
+
+        JudgeFlags::parse(&help)
+    }
+
+    pub fn parse(help: &str) -> JudgeFlags {
+        JudgeFlags {
+            flags: help
+                .split(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+                .filter(|word| word.len() > 2 && word.starts_with("--"))
+                .map(String::from)
+                .collect(),
+        }
+    }
+
+    pub fn supports(&self, flag: &str) -> bool {
+        self.flags.contains(flag)
+    }
+}
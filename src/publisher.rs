@@ -0,0 +1,145 @@
+use crate::error::ControllerError;
+use crate::scoring::ScoredVerdict;
+use crate::state::JudgeState;
+use lapin::{options::*, types::FieldTable, BasicProperties, Channel, Connection, ExchangeKind};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Publishes verdicts and progress of submissions to an AMQP exchange, so that other services
+/// can consume the results without going through the HTTP API.
+///
+/// Messages are published with routing keys `submission.<id>.verdict`,
+/// `submission.<id>.progress` and `submission.<id>.state` to a topic exchange.
+///
+/// Verdicts are published on a channel of their own in confirm mode, so that a verdict only counts
+/// as published once the server has confirmed it. Only one verdict is published on it at a time,
+/// so that the confirms waited for are those of that verdict alone.
+#[derive(Clone)]
+pub struct ResultPublisher {
+    channel: Channel,
+    verdict_channel: Arc<Mutex<Channel>>,
+    exchange: String,
+}
+
+impl ResultPublisher {
+    /// Declare the result exchange on channels of `connection` and create a publisher for it.
+    pub async fn declare(
+        connection: &Connection,
+        exchange: &str,
+    ) -> Result<ResultPublisher, lapin::Error> {
+        let channel = connection.create_channel().await?;
+        let verdict_channel = connection.create_channel().await?;
+        verdict_channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await?;
+        channel
+            .exchange_declare(
+                exchange,
+                ExchangeKind::Topic,
+                ExchangeDeclareOptions {
+                    durable: true,
+                    ..ExchangeDeclareOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+        log::info!("Declared result exchange {}.", exchange);
+
+        Ok(ResultPublisher {
+            channel,
+            verdict_channel: Arc::new(Mutex::new(verdict_channel)),
+            exchange: exchange.to_string(),
+        })
+    }
+
+    pub async fn publish_verdict(
+        &self,
+        submission_id: i32,
+        verdict: &ScoredVerdict<'_>,
+    ) -> Result<(), ControllerError> {
+        let verdict_channel = self.verdict_channel.lock().await;
+        self.publish(
+            &verdict_channel,
+            &format!("submission.{}.verdict", submission_id),
+            &json!({
+                "submission_id": submission_id,
+                "verdict": verdict,
+            }),
+        )
+        .await
+        .map_err(ControllerError::Publish)?;
+
+        // The delivery of the submission may only be acknowledged once the server has taken over
+        // the verdict.
+        let returned = verdict_channel
+            .wait_for_confirms()
+            .await
+            .map_err(ControllerError::Publish)?;
+        if !returned.is_empty() {
+            return Err(ControllerError::Unconfirmed(format!(
+                "The verdict of submission {} was rejected by the AMQP server.",
+                submission_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub async fn publish_progress(
+        &self,
+        submission_id: i32,
+        progress: i32,
+        total: i32,
+    ) -> Result<(), lapin::Error> {
+        self.publish(
+            &self.channel,
+            &format!("submission.{}.progress", submission_id),
+            &json!({
+                "submission_id": submission_id,
+                "progress": progress,
+                "total": total,
+            }),
+        )
+        .await
+    }
+
+    pub async fn publish_state(
+        &self,
+        submission_id: i32,
+        state: JudgeState,
+    ) -> Result<(), lapin::Error> {
+        self.publish(
+            &self.channel,
+            &format!("submission.{}.state", submission_id),
+            &json!({
+                "submission_id": submission_id,
+                "state": state,
+            }),
+        )
+        .await
+    }
+
+    async fn publish(
+        &self,
+        channel: &Channel,
+        routing_key: &str,
+        payload: &serde_json::Value,
+    ) -> Result<(), lapin::Error> {
+        let properties = BasicProperties::default()
+            .with_content_type("application/json".into())
+            .with_delivery_mode(2);
+
+        channel
+            .basic_publish(
+                &self.exchange,
+                routing_key,
+                BasicPublishOptions::default(),
+                payload.to_string().into_bytes(),
+                properties,
+            )
+            .await?;
+
+        Ok(())
+    }
+}
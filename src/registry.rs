@@ -0,0 +1,163 @@
+use crate::api::*;
+use crate::capabilities::Capabilities;
+use crate::cli::{self, Opts};
+use crate::error::ControllerError;
+use crate::session::Session;
+use crate::shutdown::Trigger;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The identity of this controller and the submissions it is judging, as reported to the judge
+/// server.
+pub struct Registry {
+    pub name: String,
+    judging: Mutex<BTreeSet<i32>>,
+}
+
+impl Registry {
+    pub fn new(opts: &Opts) -> Registry {
+        Registry {
+            name: opts.name.clone().unwrap_or_else(default_name),
+            judging: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// Record that the submission is being judged.
+    pub fn start(&self, submission_id: i32) {
+        self.judging.lock().unwrap().insert(submission_id);
+    }
+
+    /// Record that the submission is no longer being judged.
+    pub fn finish(&self, submission_id: i32) {
+        self.judging.lock().unwrap().remove(&submission_id);
+    }
+
+    pub fn judging(&self) -> Vec<i32> {
+        self.judging.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// The host name followed by the process ID, so that controllers sharing a host can be told
+/// apart.
+fn default_name() -> String {
+    let mut buffer = [0u8; 256];
+    let result =
+        unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+    let hostname = if result == 0 {
+        let length = buffer
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(buffer.len());
+        String::from_utf8_lossy(&buffer[..length]).to_string()
+    } else {
+        String::from("unknown")
+    };
+
+    format!("{}-{}", hostname, std::process::id())
+}
+
+/// Register the controller with the judge server and keep sending heartbeats until the process
+/// exits. The controller registers again if the server no longer knows about it.
+pub fn spawn_heartbeat(
+    opts: Arc<Opts>,
+    capabilities: Arc<Capabilities>,
+    registry: Arc<Registry>,
+    stop: Trigger,
+) {
+    tokio::spawn(async move {
+        let interval = Duration::from_secs(opts.heartbeat_interval);
+        let mut session = Session::new(&opts.server);
+        while let Err(err) = session.init(&opts.username, &opts.password).await {
+            log::error!("Failed to register with the judge server: {}", err);
+            tokio::time::delay_for(interval).await;
+        }
+        let client = reqwest::Client::new();
+        let mut controller_id = None;
+
+        loop {
+            if controller_id.is_none() {
+                match register(&client, &mut session, &opts, &capabilities, &registry).await {
+                    Ok(id) => {
+                        log::info!("Registered with the judge server as controller {}.", id);
+                        controller_id = Some(id);
+                    }
+                    Err(err) => log::error!("{}", err),
+                }
+            }
+
+            if let Some(id) = controller_id {
+                let heartbeat = ControllerHeartbeat {
+                    submissions: registry.judging(),
+                    draining: stop.is_fired(),
+                };
+                if let Err(err) = send_heartbeat(&client, &mut session, id, &heartbeat).await {
+                    log::error!("{}", err);
+                    if let ControllerError::Network(_, err) = &err {
+                        if err.status() == Some(reqwest::StatusCode::NOT_FOUND) {
+                            controller_id = None;
+                        }
+                    }
+                }
+            }
+
+            tokio::time::delay_for(interval).await;
+        }
+    });
+}
+
+async fn register(
+    client: &reqwest::Client,
+    session: &mut Session,
+    opts: &Opts,
+    capabilities: &Capabilities,
+    registry: &Registry,
+) -> Result<i32, ControllerError> {
+    let registration = ControllerRegistration {
+        name: registry.name.clone(),
+        version: cli::VERSION.to_string(),
+        sandboxes: opts.sandboxes,
+        workers: opts.workers,
+        languages: capabilities.languages.clone(),
+        interactive: capabilities.interactive,
+        max_memory: capabilities.max_memory,
+    };
+
+    let context = "Failed to register with the judge server";
+    let controller = client
+        .post(session.resolve_single("controller/register"))
+        .bearer_auth(session.get_access_token().await?)
+        .json(&registration)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(ControllerError::network(context))?
+        .json::<ApiSuccess<RegisteredController>>()
+        .await
+        .map_err(ControllerError::network(context))?
+        .data;
+
+    Ok(controller.id)
+}
+
+async fn send_heartbeat(
+    client: &reqwest::Client,
+    session: &mut Session,
+    controller_id: i32,
+    heartbeat: &ControllerHeartbeat,
+) -> Result<(), ControllerError> {
+    client
+        .put(session.resolve(vec![
+            "controller/",
+            &format!("{}/", controller_id),
+            "heartbeat",
+        ]))
+        .bearer_auth(session.get_access_token().await?)
+        .json(heartbeat)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(ControllerError::network("Failed to send heartbeat"))?;
+
+    Ok(())
+}
@@ -0,0 +1,216 @@
+use crate::problem::{GroupPolicy, ProblemMetadata, Scoring};
+use serde::Serialize;
+
+/// The score of a submission, broken down by testcase group.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreBreakdown {
+    pub score: f64,
+    pub max_score: f64,
+    pub groups: Vec<GroupScore>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupScore {
+    pub name: String,
+    pub policy: GroupPolicy,
+    pub score: f64,
+    pub max_score: f64,
+    /// The number of accepted testcases in the group.
+    pub accepted: usize,
+    /// The number of judged testcases in the group.
+    pub judged: usize,
+}
+
+/// The verdict of a submission together with its score breakdown, as pushed to the server.
+#[derive(Serialize)]
+pub struct ScoredVerdict<'a> {
+    #[serde(flatten)]
+    pub verdict: &'a judge_definitions::JudgeOutput,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<&'a ScoreBreakdown>,
+}
+
+/// Aggregate the testcase results of `verdict` into the scores of the groups of the problem.
+/// `judged` lists the indices of the testcases in the problem which have been passed to the
+/// judge, in the order of the testcases in the verdict. `None` is returned unless the problem is
+/// scored partially.
+pub fn score(
+    problem: &ProblemMetadata,
+    judged: &[usize],
+    verdict: &judge_definitions::JudgeOutput,
+) -> Option<ScoreBreakdown> {
+    if problem.scoring != Scoring::Partial || problem.groups.is_empty() {
+        return None;
+    }
+
+    // Testcases missing from the verdict, e.g. as the judge stopped early, count as failed.
+    let results: Vec<(usize, bool)> = judged
+        .iter()
+        .enumerate()
+        .map(|(position, &index)| {
+            let accepted = match verdict.testcases.get(position) {
+                Some(testcase) => testcase.verdict == judge_definitions::verdicts::VERDICT_AC,
+                None => false,
+            };
+            (index, accepted)
+        })
+        .collect();
+
+    let groups: Vec<GroupScore> = problem
+        .groups
+        .iter()
+        .map(|group| {
+            let in_group =
+                |index: usize| problem.testcases[index].group.as_ref() == Some(&group.name);
+            let total = (0..problem.testcases.len())
+                .filter(|&index| in_group(index))
+                .count();
+            let judged = results.iter().filter(|(index, _)| in_group(*index)).count();
+            let accepted = results
+                .iter()
+                .filter(|(index, accepted)| *accepted && in_group(*index))
+                .count();
+
+            // Testcases of the group which have not been judged count as failed.
+            let fraction = match group.policy {
+                _ if total == 0 => 0.,
+                GroupPolicy::Sum => accepted as f64 / total as f64,
+                GroupPolicy::AllOrNothing if accepted == total => 1.,
+                GroupPolicy::AllOrNothing => 0.,
+            };
+
+            GroupScore {
+                name: group.name.clone(),
+                policy: group.policy,
+                score: group.score * fraction,
+                max_score: group.score,
+                accepted,
+                judged,
+            }
+        })
+        .collect();
+
+    Some(ScoreBreakdown {
+        score: groups.iter().map(|group| group.score).sum(),
+        max_score: groups.iter().map(|group| group.max_score).sum(),
+        groups,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A problem with the testcases `a1`, `a2`, `b1`, `b2` and `b3` in the groups named by their
+    /// first letter, and an empty group `c`.
+    fn problem(policy: &str) -> ProblemMetadata {
+        let problem: ProblemMetadata = serde_yaml::from_str(&format!(
+            "type: standard
+last_update: 2020-01-01T00:00:00Z
+scoring: partial
+testcases:
+  - {{ name: a1, group: a }}
+  - {{ name: a2, group: a }}
+  - {{ name: b1, group: b }}
+  - {{ name: b2, group: b }}
+  - {{ name: b3, group: b }}
+groups:
+  - {{ name: a, score: 40, policy: {policy} }}
+  - {{ name: b, score: 60, policy: {policy} }}
+  - {{ name: c, score: 10, policy: {policy} }}
+",
+            policy = policy
+        ))
+        .unwrap();
+        problem.validate().unwrap();
+        problem
+    }
+
+    /// The output of the judge with the given verdicts of the testcases.
+    fn verdict(testcases: &[&str]) -> judge_definitions::JudgeOutput {
+        let testcases: Vec<_> = testcases
+            .iter()
+            .map(|verdict| serde_json::json!({ "verdict": verdict, "time": 0.1, "memory": 1024 }))
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "verdict": "WA",
+            "time": 0.1,
+            "memory": 1024,
+            "compile_message": "",
+            "testcases": testcases,
+        }))
+        .unwrap()
+    }
+
+    fn scores(breakdown: &ScoreBreakdown) -> Vec<(&str, f64, usize, usize)> {
+        breakdown
+            .groups
+            .iter()
+            .map(|group| {
+                (
+                    group.name.as_str(),
+                    group.score,
+                    group.accepted,
+                    group.judged,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sum_awards_an_equal_share_per_accepted_testcase() {
+        let breakdown = score(
+            &problem("sum"),
+            &[0, 1, 2, 3, 4],
+            &verdict(&["AC", "WA", "AC", "TLE", "AC"]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            scores(&breakdown),
+            vec![("a", 20., 1, 2), ("b", 40., 2, 3), ("c", 0., 0, 0)]
+        );
+        assert_eq!(breakdown.score, 60.);
+        assert_eq!(breakdown.max_score, 110.);
+    }
+
+    #[test]
+    fn all_or_nothing_awards_only_fully_accepted_groups() {
+        let breakdown = score(
+            &problem("all_or_nothing"),
+            &[0, 1, 2, 3, 4],
+            &verdict(&["AC", "AC", "AC", "WA", "AC"]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            scores(&breakdown),
+            vec![("a", 40., 2, 2), ("b", 0., 2, 3), ("c", 0., 0, 0)]
+        );
+        assert_eq!(breakdown.score, 40.);
+    }
+
+    #[test]
+    fn testcases_not_judged_count_as_failed() {
+        // Only a1, a2 and b2 are judged, and the judge stopped before b2.
+        let problem = problem("all_or_nothing");
+        let breakdown = score(&problem, &[0, 1, 3], &verdict(&["AC", "AC"])).unwrap();
+
+        assert_eq!(
+            scores(&breakdown),
+            vec![("a", 40., 2, 2), ("b", 0., 0, 1), ("c", 0., 0, 0)]
+        );
+
+        let breakdown = score(&problem, &[], &verdict(&[])).unwrap();
+        assert_eq!(breakdown.score, 0.);
+        assert_eq!(breakdown.max_score, 110.);
+    }
+
+    #[test]
+    fn binary_scoring_has_no_breakdown() {
+        let mut problem = problem("sum");
+        problem.scoring = Scoring::Binary;
+
+        assert!(score(&problem, &[0], &verdict(&["AC"])).is_none());
+    }
+}
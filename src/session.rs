@@ -1,4 +1,5 @@
 use crate::api::*;
+use crate::error::ControllerError;
 use chrono::{DateTime, Duration, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 use std::clone::Clone;
@@ -27,7 +28,7 @@ pub struct JWTClaims {
 impl Session {
     pub fn new(base_url: &str) -> Session {
         Session {
-            base_url: Url::parse(base_url).unwrap(),
+            base_url: Url::parse(base_url).expect("Invalid server URL."),
             expiry: Utc.timestamp(0, 0),
             access_token: String::new(),
             refresh_token: String::new(),
@@ -38,7 +39,7 @@ impl Session {
         url_fragment
             .iter()
             .fold(self.base_url.clone(), |url, fragment| {
-                url.join(fragment).unwrap()
+                url.join(fragment).expect("Invalid URL fragment.")
             })
     }
 
@@ -46,63 +47,69 @@ impl Session {
         self.resolve(vec![url_fragment])
     }
 
-    pub async fn init(&mut self, username: &str, password: &str) {
-        let client = reqwest::Client::new();
+    pub async fn init(&mut self, username: &str, password: &str) -> Result<(), ControllerError> {
         let body = {
             let mut map = HashMap::new();
             map.insert("username", username);
             map.insert("password", password);
             map
         };
-        let response = client
-            .post(self.resolve_single("auth/login"))
-            .json(&body)
-            .send()
-            .await
-            .unwrap()
-            .json::<ApiSuccess<JWTTokenPair>>()
-            .await
-            .unwrap();
-
-        self.access_token = String::from(response.data.access_token);
-        self.refresh_token = String::from(response.data.refresh_token);
-
-        self.recalc_expiry();
+        let response = request_tokens(self.resolve_single("auth/login"), &body).await?;
+
+        self.access_token = String::from(response.access_token);
+        self.refresh_token = String::from(response.refresh_token);
+
+        self.recalc_expiry()
     }
 
     /// Recompute expiry time from `self.access_token`.
-    pub fn recalc_expiry(&mut self) {
-        let token_message =
-            jsonwebtoken::dangerous_unsafe_decode::<JWTClaims>(&self.access_token).unwrap();
+    pub fn recalc_expiry(&mut self) -> Result<(), ControllerError> {
+        let token_message = jsonwebtoken::dangerous_unsafe_decode::<JWTClaims>(&self.access_token)
+            .map_err(|err| {
+                ControllerError::Auth(format!("Failed to decode access token: {}", err))
+            })?;
         self.expiry = Utc.timestamp(token_message.claims.exp, 0);
+        Ok(())
     }
 
-    pub async fn refresh(&mut self) {
-        let client = reqwest::Client::new();
+    pub async fn refresh(&mut self) -> Result<(), ControllerError> {
+        let refresh_token = self.refresh_token.clone();
         let body = {
             let mut map = HashMap::new();
-            map.insert("refresh_token", &self.refresh_token);
+            map.insert("refresh_token", refresh_token.as_str());
             map
         };
-        let response = client
-            .post(self.resolve_single("auth/refresh"))
-            .json(&body)
-            .send()
-            .await
-            .unwrap()
-            .json::<ApiSuccess<JWTTokenPair>>()
-            .await
-            .unwrap();
-
-        self.access_token = String::from(response.data.access_token);
-        self.recalc_expiry();
+        let response = request_tokens(self.resolve_single("auth/refresh"), &body).await?;
+
+        self.access_token = String::from(response.access_token);
+        self.recalc_expiry()
     }
 
-    pub async fn get_access_token(&mut self) -> &str {
+    pub async fn get_access_token(&mut self) -> Result<&str, ControllerError> {
         if Utc::now() + Duration::minutes(5) > self.expiry {
-            self.refresh().await;
+            self.refresh().await?;
         }
 
-        &self.access_token
+        Ok(&self.access_token)
     }
 }
+
+async fn request_tokens(
+    url: Url,
+    body: &HashMap<&str, &str>,
+) -> Result<JWTTokenPair, ControllerError> {
+    let auth_error = |err: reqwest::Error| ControllerError::Auth(err.to_string());
+    let response = reqwest::Client::new()
+        .post(url)
+        .json(body)
+        .send()
+        .await
+        .map_err(auth_error)?
+        .error_for_status()
+        .map_err(auth_error)?
+        .json::<ApiSuccess<JWTTokenPair>>()
+        .await
+        .map_err(auth_error)?;
+
+    Ok(response.data)
+}
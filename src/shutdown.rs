@@ -0,0 +1,61 @@
+use futures_util::future;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+
+/// A flag that is raised at most once and can be observed by any number of tasks.
+#[derive(Clone)]
+pub struct Trigger {
+    receiver: watch::Receiver<bool>,
+}
+
+/// The handle raising the flag of the associated `Trigger`s.
+pub struct TriggerHandle {
+    sender: watch::Sender<bool>,
+}
+
+pub fn trigger() -> (TriggerHandle, Trigger) {
+    let (sender, receiver) = watch::channel(false);
+    (TriggerHandle { sender }, Trigger { receiver })
+}
+
+impl TriggerHandle {
+    pub fn fire(&self) {
+        let _ = self.sender.broadcast(true);
+    }
+}
+
+impl Trigger {
+    pub fn is_fired(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Wait until the flag is raised.
+    pub async fn fired(&mut self) {
+        loop {
+            match self.receiver.recv().await {
+                Some(true) => return,
+                Some(false) => continue,
+                // The flag can no longer be raised.
+                None => future::pending().await,
+            }
+        }
+    }
+}
+
+/// Listen for SIGINT and SIGTERM, returning a trigger which is fired once either is received.
+pub fn listen_for_signals() -> Trigger {
+    let (handle, trigger) = trigger();
+
+    tokio::spawn(async move {
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM.");
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => log::warn!("Received SIGINT. Shutting down..."),
+            _ = terminate.recv() => log::warn!("Received SIGTERM. Shutting down..."),
+        }
+
+        handle.fire();
+    });
+
+    trigger
+}
@@ -0,0 +1,385 @@
+use super::{JobSource, Outcome, ReceivedJob, Ticket};
+use crate::amqp::{self, AmqpSession, Backoff};
+use crate::capabilities::Capabilities;
+use crate::cli::Opts;
+use crate::job::Job;
+use crate::publisher::ResultPublisher;
+use futures_util::future::{BoxFuture, FutureExt};
+use futures_util::stream::StreamExt;
+use lapin::{
+    message::Delivery,
+    options::*,
+    types::{AMQPValue, FieldTable, ShortString},
+    BasicProperties, Channel,
+};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// The header carrying the reason of the last failure of a delivery.
+pub const FAILURE_REASON_HEADER: &str = "x-failure-reason";
+
+/// The header counting how many times the delivery has been attempted.
+pub const ATTEMPTS_HEADER: &str = "x-attempts";
+
+/// Takes jobs from the judge queues on the AMQP server, reconnecting whenever the connection is
+/// lost.
+pub struct AmqpSource {
+    opts: Arc<Opts>,
+    queues: Vec<String>,
+    backoff: Backoff,
+    session: Option<AmqpSession>,
+}
+
+/// An unacknowledged delivery of the judge queue.
+///
+/// The delivery is only acknowledged once the verdict has been accepted by the server. Failed
+/// deliveries are retried until `--max-attempts` is reached, after which they are dead-lettered.
+/// Deliveries aborted by a shutdown or rejected by this host are requeued as they are.
+struct AmqpTicket {
+    opts: Arc<Opts>,
+    queue: String,
+    channel: Channel,
+    republish_channel: Arc<Mutex<Channel>>,
+    delivery: Delivery,
+    job: Job,
+}
+
+impl AmqpSource {
+    pub fn new(opts: Arc<Opts>, capabilities: &Capabilities) -> AmqpSource {
+        let queues = if opts.capability_routing {
+            capabilities.queues(&opts.queue)
+        } else {
+            vec![opts.queue.clone()]
+        };
+
+        AmqpSource {
+            backoff: Backoff::new(&opts),
+            opts,
+            queues,
+            session: None,
+        }
+    }
+}
+
+impl JobSource for AmqpSource {
+    fn next(&mut self) -> BoxFuture<'_, Option<ReceivedJob>> {
+        async move {
+            loop {
+                if self.session.is_none() {
+                    match amqp::connect(&self.opts, &self.queues).await {
+                        Ok(session) => {
+                            self.backoff.reset();
+                            self.session = Some(session);
+                        }
+                        Err(err) => {
+                            let delay = self.backoff.next_delay();
+                            log::error!(
+                                "Failed to connect to AMQP server: {}. Retrying in {:?}.",
+                                err,
+                                delay
+                            );
+                            tokio::time::delay_for(delay).await;
+                            continue;
+                        }
+                    }
+                }
+
+                let session = self.session.as_mut().unwrap();
+                let (queue, delivery) = match session.consumer.next().await {
+                    Some((queue, Ok(delivery))) => (queue, delivery),
+                    Some((_, Err(err))) => {
+                        // In-flight jobs keep running and push their verdicts. Their deliveries
+                        // are returned to the queue by the server as the channel is gone.
+                        log::error!("Failed to receive delivery: {}", err);
+                        log::warn!("Lost connection to AMQP server. Reconnecting...");
+                        self.session = None;
+                        continue;
+                    }
+                    None => {
+                        log::warn!("Lost connection to AMQP server. Reconnecting...");
+                        self.session = None;
+                        continue;
+                    }
+                };
+
+                let job = match Job::parse(&delivery.data) {
+                    Ok(job) => job,
+                    Err(reason) => {
+                        log::error!("{}", reason);
+                        dead_letter(
+                            &self.opts,
+                            &session.channel,
+                            &session.republish_channel,
+                            &delivery,
+                            &reason,
+                        )
+                        .await;
+                        continue;
+                    }
+                };
+
+                return Some(ReceivedJob {
+                    job: job.clone(),
+                    ticket: Box::new(AmqpTicket {
+                        opts: self.opts.clone(),
+                        queue,
+                        channel: session.channel.clone(),
+                        republish_channel: session.republish_channel.clone(),
+                        delivery,
+                        job,
+                    }),
+                });
+            }
+        }
+        .boxed()
+    }
+
+    fn stop(&mut self) -> BoxFuture<'_, ()> {
+        async move {
+            if let Some(channel) = self.session.as_ref().map(|session| session.channel.clone()) {
+                log::info!("Stopping consumer...");
+                for queue in &self.queues {
+                    if let Err(err) = channel
+                        .basic_cancel(
+                            &amqp::consumer_tag(&self.opts, queue),
+                            BasicCancelOptions::default(),
+                        )
+                        .await
+                    {
+                        log::error!("Failed to stop consumer of queue {}: {}", queue, err);
+                    }
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, ()> {
+        async move {
+            if let Some(session) = self.session.take() {
+                if let Err(err) = session
+                    .connection
+                    .close(200, "Controller shutting down")
+                    .await
+                {
+                    log::error!("Failed to close AMQP connection: {}", err);
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn publisher(&self) -> Option<ResultPublisher> {
+        self.session
+            .as_ref()
+            .and_then(|session| session.publisher.clone())
+    }
+}
+
+impl Ticket for AmqpTicket {
+    fn settle(self: Box<Self>, outcome: Outcome) -> BoxFuture<'static, ()> {
+        async move {
+            match outcome {
+                Outcome::Done | Outcome::Skipped => ack(&self.channel, &self.delivery).await,
+                Outcome::Failed(reason) => {
+                    retry(
+                        &self.opts,
+                        &self.queue,
+                        &self.channel,
+                        &self.republish_channel,
+                        &self.delivery,
+                        &self.job,
+                        &reason,
+                    )
+                    .await
+                }
+                Outcome::Aborted => {
+                    log::warn!("Requeueing aborted submission {}.", self.job.submission_id);
+                    nack(&self.channel, &self.delivery, true).await;
+                }
+                Outcome::Rejected(_) => {
+                    log::warn!(
+                        "Requeueing submission {} for another host.",
+                        self.job.submission_id
+                    );
+                    nack(&self.channel, &self.delivery, true).await;
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Put a failed delivery back onto `queue` with its attempt counter increased, or
+/// dead-letter it if it has used up all of its attempts. The retried delivery keeps the priority
+/// of the job.
+async fn retry(
+    opts: &Opts,
+    queue: &str,
+    channel: &Channel,
+    republish_channel: &Mutex<Channel>,
+    delivery: &Delivery,
+    job: &Job,
+    reason: &str,
+) {
+    let attempts = get_attempts(delivery) + 1;
+    if attempts >= opts.max_attempts {
+        log::warn!("Delivery failed {} times, giving up.", attempts);
+        dead_letter(opts, channel, republish_channel, delivery, reason).await;
+        return;
+    }
+
+    log::warn!("Requeueing delivery (attempt {}).", attempts);
+    let priority = delivery
+        .properties
+        .priority()
+        .unwrap_or(0)
+        .max(job.priority);
+    match republish(
+        republish_channel,
+        queue,
+        delivery,
+        reason,
+        attempts,
+        Some(priority),
+    )
+    .await
+    {
+        Ok(()) => ack(channel, delivery).await,
+        Err(err) => {
+            log::error!("Failed to republish delivery: {}", err);
+            nack(channel, delivery, true).await;
+        }
+    }
+}
+
+/// Route a delivery that cannot be processed to the dead-letter queue, or reject it if no
+/// dead-letter queue is configured.
+async fn dead_letter(
+    opts: &Opts,
+    channel: &Channel,
+    republish_channel: &Mutex<Channel>,
+    delivery: &Delivery,
+    reason: &str,
+) {
+    let dead_letter_queue = match &opts.dead_letter_queue {
+        Some(dead_letter_queue) => dead_letter_queue,
+        None => {
+            log::warn!("No dead-letter queue configured. Rejecting delivery.");
+            nack(channel, delivery, false).await;
+            return;
+        }
+    };
+
+    log::warn!(
+        "Routing delivery to dead-letter queue {}.",
+        dead_letter_queue
+    );
+    let attempts = get_attempts(delivery) + 1;
+    match republish(
+        republish_channel,
+        dead_letter_queue,
+        delivery,
+        reason,
+        attempts,
+        None,
+    )
+    .await
+    {
+        Ok(()) => ack(channel, delivery).await,
+        Err(err) => {
+            log::error!("Failed to dead-letter delivery: {}", err);
+            nack(channel, delivery, true).await;
+        }
+    }
+}
+
+/// Read the attempt counter of `delivery`, which is absent on the first attempt.
+fn get_attempts(delivery: &Delivery) -> i32 {
+    delivery
+        .properties
+        .headers()
+        .as_ref()
+        .and_then(|headers| headers.inner().get(&ShortString::from(ATTEMPTS_HEADER)))
+        .and_then(|value| match value {
+            AMQPValue::LongInt(attempts) => Some(*attempts),
+            AMQPValue::LongLongInt(attempts) => Some(*attempts as i32),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+/// Publish a copy of `delivery` to `queue` with the failure headers set, overriding the priority
+/// of the message if `priority` is given. Succeeds once the server has confirmed the copy.
+async fn republish(
+    republish_channel: &Mutex<Channel>,
+    queue: &str,
+    delivery: &Delivery,
+    reason: &str,
+    attempts: i32,
+    priority: Option<u8>,
+) -> Result<(), String> {
+    let mut headers: FieldTable = delivery.properties.headers().clone().unwrap_or_default();
+    headers.insert(
+        ShortString::from(FAILURE_REASON_HEADER),
+        AMQPValue::LongString(reason.into()),
+    );
+    headers.insert(
+        ShortString::from(ATTEMPTS_HEADER),
+        AMQPValue::LongInt(attempts),
+    );
+    let mut properties: BasicProperties = delivery.properties.clone().with_headers(headers);
+    if let Some(priority) = priority {
+        properties = properties.with_priority(priority);
+    }
+
+    // The copy is published as mandatory, so that it is returned rather than dropped if the
+    // queue does not exist.
+    let channel = republish_channel.lock().await;
+    channel
+        .basic_publish(
+            "",
+            queue,
+            BasicPublishOptions {
+                mandatory: true,
+                ..BasicPublishOptions::default()
+            },
+            delivery.data.clone(),
+            properties,
+        )
+        .await
+        .map_err(|err| err.to_string())?;
+    let returned = channel
+        .wait_for_confirms()
+        .await
+        .map_err(|err| err.to_string())?;
+    if !returned.is_empty() {
+        return Err("The copy was rejected by the AMQP server.".to_string());
+    }
+
+    Ok(())
+}
+
+async fn ack(channel: &Channel, delivery: &Delivery) {
+    if let Err(err) = channel
+        .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
+        .await
+    {
+        log::error!("Failed to acknowledge delivery: {}", err);
+    }
+}
+
+async fn nack(channel: &Channel, delivery: &Delivery, requeue: bool) {
+    if let Err(err) = channel
+        .basic_nack(
+            delivery.delivery_tag,
+            BasicNackOptions {
+                requeue,
+                ..BasicNackOptions::default()
+            },
+        )
+        .await
+    {
+        log::error!("Failed to reject delivery: {}", err);
+    }
+}
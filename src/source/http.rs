@@ -0,0 +1,140 @@
+use super::{JobSource, Outcome, ReceivedJob, Ticket};
+use crate::api::ApiSuccess;
+use crate::cli::Opts;
+use crate::error::ControllerError;
+use crate::job::Job;
+use crate::session::Session;
+use futures_util::future::{BoxFuture, FutureExt};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Polls the judge server for pending submissions.
+///
+/// This requires an endpoint the judge server does not necessarily provide:
+/// `GET submission/pending` has to respond with the IDs of the submissions waiting to be judged,
+/// as `{"success": true, "data": [<id>, ...]}`, to the same credentials as the other requests of
+/// the controller.
+///
+/// The server keeps listing a submission as pending until its verdict has been pushed, so failed
+/// jobs are retried simply by picking them up again. Submissions that failed `--max-attempts`
+/// times are skipped from then on.
+pub struct HttpSource {
+    opts: Arc<Opts>,
+    session: Option<Session>,
+    pending: VecDeque<i32>,
+    state: Arc<Mutex<HttpSourceState>>,
+}
+
+#[derive(Default)]
+struct HttpSourceState {
+    in_flight: HashSet<i32>,
+    attempts: HashMap<i32, i32>,
+}
+
+struct HttpTicket {
+    max_attempts: i32,
+    submission_id: i32,
+    state: Arc<Mutex<HttpSourceState>>,
+}
+
+impl HttpSource {
+    pub fn new(opts: Arc<Opts>) -> HttpSource {
+        HttpSource {
+            opts,
+            session: None,
+            pending: VecDeque::new(),
+            state: Arc::new(Mutex::new(HttpSourceState::default())),
+        }
+    }
+
+    async fn fetch_pending(&mut self) -> Result<Vec<i32>, ControllerError> {
+        if self.session.is_none() {
+            let mut session = Session::new(&self.opts.server);
+            session
+                .init(&self.opts.username, &self.opts.password)
+                .await?;
+            self.session = Some(session);
+        }
+        let session = self.session.as_mut().unwrap();
+
+        let context = "Failed to fetch pending submissions";
+        let url = session.resolve_single("submission/pending");
+        let pending = reqwest::Client::new()
+            .get(url)
+            .bearer_auth(session.get_access_token().await?)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(ControllerError::network(context))?
+            .json::<ApiSuccess<Vec<i32>>>()
+            .await
+            .map_err(ControllerError::network(context))?
+            .data;
+
+        Ok(pending)
+    }
+}
+
+impl JobSource for HttpSource {
+    fn next(&mut self) -> BoxFuture<'_, Option<ReceivedJob>> {
+        async move {
+            loop {
+                while let Some(submission_id) = self.pending.pop_front() {
+                    let mut state = self.state.lock().unwrap();
+                    let attempts = state.attempts.get(&submission_id).cloned().unwrap_or(0);
+                    if attempts >= self.opts.max_attempts || !state.in_flight.insert(submission_id)
+                    {
+                        continue;
+                    }
+
+                    return Some(ReceivedJob {
+                        job: Job::new(submission_id),
+                        ticket: Box::new(HttpTicket {
+                            max_attempts: self.opts.max_attempts,
+                            submission_id,
+                            state: self.state.clone(),
+                        }),
+                    });
+                }
+
+                match self.fetch_pending().await {
+                    Ok(pending) => self.pending.extend(pending),
+                    Err(err) => log::error!("{}", err),
+                }
+                if self.pending.is_empty() {
+                    tokio::time::delay_for(Duration::from_secs(self.opts.poll_interval)).await;
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+impl Ticket for HttpTicket {
+    fn settle(self: Box<Self>, outcome: Outcome) -> BoxFuture<'static, ()> {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight.remove(&self.submission_id);
+
+        match outcome {
+            Outcome::Done | Outcome::Skipped => {
+                state.attempts.remove(&self.submission_id);
+            }
+            Outcome::Failed(reason) => {
+                let count = state.attempts.entry(self.submission_id).or_insert(0);
+                *count += 1;
+                if *count >= self.max_attempts {
+                    log::error!(
+                        "Giving up on submission {} after {} attempts: {}",
+                        self.submission_id,
+                        count,
+                        reason
+                    );
+                }
+            }
+            Outcome::Aborted | Outcome::Rejected(_) => {}
+        }
+
+        futures_util::future::ready(()).boxed()
+    }
+}
@@ -0,0 +1,72 @@
+use super::{JobSource, Outcome, ReceivedJob, Ticket};
+use crate::job::Job;
+use futures_util::future::{self, BoxFuture, FutureExt};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Hands out a fixed list of jobs and records how they are settled. The source is exhausted once
+/// every job has been handed out, unless it is kept open, in which case it waits until stopped.
+pub struct MemorySource {
+    jobs: VecDeque<Job>,
+    keep_open: bool,
+    stopped: Arc<Mutex<bool>>,
+    outcomes: Arc<Mutex<Vec<(i32, Outcome)>>>,
+}
+
+struct MemoryTicket {
+    submission_id: i32,
+    outcomes: Arc<Mutex<Vec<(i32, Outcome)>>>,
+}
+
+impl MemorySource {
+    pub fn new(jobs: Vec<Job>, keep_open: bool) -> MemorySource {
+        MemorySource {
+            jobs: jobs.into(),
+            keep_open,
+            stopped: Arc::new(Mutex::new(false)),
+            outcomes: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// The submission IDs and outcomes of the settled jobs, in the order they were settled.
+    pub fn outcomes(&self) -> Arc<Mutex<Vec<(i32, Outcome)>>> {
+        self.outcomes.clone()
+    }
+
+    /// Whether the source has been stopped.
+    pub fn stopped(&self) -> Arc<Mutex<bool>> {
+        self.stopped.clone()
+    }
+}
+
+impl JobSource for MemorySource {
+    fn next(&mut self) -> BoxFuture<'_, Option<ReceivedJob>> {
+        match self.jobs.pop_front() {
+            Some(job) => future::ready(Some(ReceivedJob {
+                ticket: Box::new(MemoryTicket {
+                    submission_id: job.submission_id,
+                    outcomes: self.outcomes.clone(),
+                }),
+                job,
+            }))
+            .boxed(),
+            None if self.keep_open => future::pending().boxed(),
+            None => future::ready(None).boxed(),
+        }
+    }
+
+    fn stop(&mut self) -> BoxFuture<'_, ()> {
+        *self.stopped.lock().unwrap() = true;
+        future::ready(()).boxed()
+    }
+}
+
+impl Ticket for MemoryTicket {
+    fn settle(self: Box<Self>, outcome: Outcome) -> BoxFuture<'static, ()> {
+        self.outcomes
+            .lock()
+            .unwrap()
+            .push((self.submission_id, outcome));
+        future::ready(()).boxed()
+    }
+}
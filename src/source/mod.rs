@@ -0,0 +1,80 @@
+mod amqp;
+mod http;
+#[cfg(test)]
+mod memory;
+mod spool;
+mod stdin;
+
+use crate::capabilities::Capabilities;
+use crate::cli::Opts;
+use crate::job::Job;
+use crate::publisher::ResultPublisher;
+use futures_util::future::{self, BoxFuture, FutureExt};
+use std::sync::Arc;
+
+pub use self::amqp::AmqpSource;
+pub use self::http::HttpSource;
+#[cfg(test)]
+pub use self::memory::MemorySource;
+pub use self::spool::SpoolSource;
+pub use self::stdin::StdinSource;
+
+/// The outcome of processing a job, reported back to the source of the job.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// The verdict has been pushed to the server.
+    Done,
+    /// The judging failed for the given reason. The source may retry the job.
+    Failed(String),
+    /// The judging was abandoned because the controller is shutting down. The source should
+    /// hand the job out again as it is.
+    Aborted,
+    /// The submission is being judged by another controller, which pushes the verdict instead.
+    Skipped,
+    /// The job cannot be judged on this host for the given reason. The source should hand the
+    /// job out again without counting it as an attempt, so that another host can judge it.
+    Rejected(String),
+}
+
+/// A job taken from a source, together with the ticket to settle it with.
+pub struct ReceivedJob {
+    pub job: Job,
+    pub ticket: Box<dyn Ticket>,
+}
+
+/// Settles a job with its source once it has been processed.
+pub trait Ticket: Send {
+    fn settle(self: Box<Self>, outcome: Outcome) -> BoxFuture<'static, ()>;
+}
+
+/// A source the controller takes the jobs to judge from.
+pub trait JobSource: Send {
+    /// Wait for the next job. `None` is returned once the source is exhausted.
+    fn next(&mut self) -> BoxFuture<'_, Option<ReceivedJob>>;
+
+    /// Stop handing out jobs. Jobs which have already been handed out can still be settled.
+    fn stop(&mut self) -> BoxFuture<'_, ()> {
+        future::ready(()).boxed()
+    }
+
+    /// Release the source once every job has been settled.
+    fn close(&mut self) -> BoxFuture<'_, ()> {
+        future::ready(()).boxed()
+    }
+
+    /// The publisher to publish results with, if the source provides one.
+    fn publisher(&self) -> Option<ResultPublisher> {
+        None
+    }
+}
+
+/// Create the job source selected by `--source`.
+pub fn create(opts: Arc<Opts>, capabilities: &Capabilities) -> Box<dyn JobSource> {
+    match opts.source.as_str() {
+        "amqp" => Box::new(AmqpSource::new(opts, capabilities)),
+        "http" => Box::new(HttpSource::new(opts)),
+        "spool" => Box::new(SpoolSource::new(opts)),
+        "stdin" => Box::new(StdinSource::new()),
+        source => panic!("Unknown job source {}.", source),
+    }
+}
@@ -0,0 +1,204 @@
+use super::{JobSource, Outcome, ReceivedJob, Ticket};
+use crate::cli::Opts;
+use crate::job::Job;
+use futures_util::future::{BoxFuture, FutureExt};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Takes jobs from files in a local spool directory, each file holding one job in the same
+/// format as the AMQP messages.
+///
+/// A file is claimed by moving it into `processing/`. It is removed once the verdict has been
+/// pushed, and moved back to be retried if the judging failed. Malformed files and files that
+/// failed `--max-attempts` times are moved into `failed/`. Files left in `processing/` by a
+/// controller that did not settle them, e.g. as it crashed, are moved back on startup, so the
+/// spool folder must not be shared between controllers.
+pub struct SpoolSource {
+    opts: Arc<Opts>,
+    folder: PathBuf,
+    attempts: Arc<Mutex<HashMap<String, i32>>>,
+}
+
+struct SpoolTicket {
+    max_attempts: i32,
+    folder: PathBuf,
+    name: String,
+    attempts: Arc<Mutex<HashMap<String, i32>>>,
+}
+
+impl SpoolSource {
+    pub fn new(opts: Arc<Opts>) -> SpoolSource {
+        let folder = PathBuf::from(opts.spool.as_ref().expect("No spool folder given."));
+        std::fs::create_dir_all(folder.join("processing")).unwrap();
+        std::fs::create_dir_all(folder.join("failed")).unwrap();
+        recover(&folder);
+
+        SpoolSource {
+            opts,
+            folder,
+            attempts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Find the oldest file in the spool folder.
+    fn oldest_file(&self) -> std::io::Result<Option<String>> {
+        let mut oldest = None;
+        for entry in std::fs::read_dir(&self.folder)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+
+            let modified = metadata.modified()?;
+            match &oldest {
+                Some((oldest_modified, _)) if *oldest_modified <= modified => {}
+                _ => oldest = Some((modified, entry.file_name().to_string_lossy().to_string())),
+            }
+        }
+
+        Ok(oldest.map(|(_, name)| name))
+    }
+}
+
+impl JobSource for SpoolSource {
+    fn next(&mut self) -> BoxFuture<'_, Option<ReceivedJob>> {
+        async move {
+            loop {
+                let name = match self.oldest_file() {
+                    Ok(Some(name)) => name,
+                    Ok(None) => {
+                        tokio::time::delay_for(Duration::from_secs(self.opts.poll_interval)).await;
+                        continue;
+                    }
+                    Err(err) => {
+                        log::error!("Failed to read spool folder: {}", err);
+                        tokio::time::delay_for(Duration::from_secs(self.opts.poll_interval)).await;
+                        continue;
+                    }
+                };
+
+                let path = self.folder.join("processing").join(&name);
+                if let Err(err) = std::fs::rename(self.folder.join(&name), &path) {
+                    log::error!("Failed to claim spool file {}: {}", name, err);
+                    continue;
+                }
+
+                let job = std::fs::read(&path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|payload| Job::parse(&payload));
+                match job {
+                    Ok(job) => {
+                        return Some(ReceivedJob {
+                            job,
+                            ticket: Box::new(SpoolTicket {
+                                max_attempts: self.opts.max_attempts,
+                                folder: self.folder.clone(),
+                                name,
+                                attempts: self.attempts.clone(),
+                            }),
+                        });
+                    }
+                    Err(reason) => {
+                        log::error!("{}", reason);
+                        move_file(&path, &self.folder.join("failed").join(&name));
+                    }
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+impl Ticket for SpoolTicket {
+    fn settle(self: Box<Self>, outcome: Outcome) -> BoxFuture<'static, ()> {
+        let path = self.folder.join("processing").join(&self.name);
+
+        match outcome {
+            Outcome::Done | Outcome::Skipped => {
+                self.attempts.lock().unwrap().remove(&self.name);
+                if let Err(err) = std::fs::remove_file(&path) {
+                    log::error!("Failed to remove spool file {}: {}", self.name, err);
+                }
+            }
+            Outcome::Failed(reason) => {
+                let mut attempts = self.attempts.lock().unwrap();
+                let count = attempts.entry(self.name.clone()).or_insert(0);
+                *count += 1;
+                if *count >= self.max_attempts {
+                    log::error!(
+                        "Giving up on spool file {} after {} attempts: {}",
+                        self.name,
+                        count,
+                        reason
+                    );
+                    move_file(&path, &self.folder.join("failed").join(&self.name));
+                } else {
+                    move_file(&path, &self.folder.join(&self.name));
+                }
+            }
+            Outcome::Aborted | Outcome::Rejected(_) => {
+                move_file(&path, &self.folder.join(&self.name))
+            }
+        }
+
+        futures_util::future::ready(()).boxed()
+    }
+}
+
+/// Move the files left in `processing/` back into the spool folder, to be judged again.
+fn recover(folder: &Path) {
+    let entries = match std::fs::read_dir(folder.join("processing")) {
+        Ok(entries) => entries,
+        Err(err) => {
+            log::error!("Failed to read spool folder: {}", err);
+            return;
+        }
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let name = entry.file_name();
+        log::warn!(
+            "Recovering spool file {}, which was not settled.",
+            name.to_string_lossy()
+        );
+        move_file(&entry.path(), &folder.join(&name));
+    }
+}
+
+fn move_file(from: &Path, to: &Path) {
+    if let Err(err) = std::fs::rename(from, to) {
+        log::error!(
+            "Failed to move {} to {}: {}",
+            from.display(),
+            to.display(),
+            err
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::Context;
+
+    #[test]
+    fn recovers_files_left_in_processing() {
+        let context = Context::for_tests(&[]);
+        let folder = PathBuf::from(&context.opts.temp).join("spool");
+        std::fs::create_dir_all(folder.join("processing")).unwrap();
+        std::fs::write(folder.join("processing").join("1234"), "1234").unwrap();
+        let opts = Opts {
+            spool: Some(folder.to_str().unwrap().to_string()),
+            ..(*context.opts).clone()
+        };
+
+        let source = SpoolSource::new(Arc::new(opts));
+
+        assert_eq!(source.oldest_file().unwrap(), Some("1234".to_string()));
+        assert!(!folder.join("processing").join("1234").exists());
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
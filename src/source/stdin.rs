@@ -0,0 +1,70 @@
+use super::{JobSource, Outcome, ReceivedJob, Ticket};
+use crate::job::Job;
+use futures_util::future::{BoxFuture, FutureExt};
+use tokio::io::{AsyncBufReadExt, BufReader, Lines, Stdin};
+
+/// Takes jobs from the lines of the standard input, each line holding one job in the same format
+/// as the AMQP messages. The source is exhausted at the end of the input.
+pub struct StdinSource {
+    lines: Lines<BufReader<Stdin>>,
+}
+
+/// Jobs from the standard input are not retried, so the outcome is only logged.
+struct StdinTicket {
+    submission_id: i32,
+}
+
+impl StdinSource {
+    pub fn new() -> StdinSource {
+        StdinSource {
+            lines: BufReader::new(tokio::io::stdin()).lines(),
+        }
+    }
+}
+
+impl JobSource for StdinSource {
+    fn next(&mut self) -> BoxFuture<'_, Option<ReceivedJob>> {
+        async move {
+            loop {
+                let line = match self.lines.next_line().await {
+                    Ok(Some(line)) => line,
+                    Ok(None) => return None,
+                    Err(err) => {
+                        log::error!("Failed to read from standard input: {}", err);
+                        return None;
+                    }
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                match Job::parse(line.as_bytes()) {
+                    Ok(job) => {
+                        return Some(ReceivedJob {
+                            ticket: Box::new(StdinTicket {
+                                submission_id: job.submission_id,
+                            }),
+                            job,
+                        })
+                    }
+                    Err(reason) => log::error!("{}", reason),
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+impl Ticket for StdinTicket {
+    fn settle(self: Box<Self>, outcome: Outcome) -> BoxFuture<'static, ()> {
+        match outcome {
+            Outcome::Done => log::info!("Submission {} judged.", self.submission_id),
+            Outcome::Failed(reason) => log::error!("{}", reason),
+            Outcome::Aborted => log::warn!("Submission {} aborted.", self.submission_id),
+            Outcome::Skipped => log::warn!("Submission {} skipped.", self.submission_id),
+            Outcome::Rejected(reason) => log::error!("{}", reason),
+        }
+
+        futures_util::future::ready(()).boxed()
+    }
+}
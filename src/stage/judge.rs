@@ -0,0 +1,331 @@
+use super::{Judging, Stage, StageResult};
+use crate::error::ControllerError;
+use crate::process::{JudgeExit, JudgeProcess, INTERACTOR_LANGUAGE_FLAG, SANDBOX_OFFSET_FLAG};
+use crate::shutdown;
+use crate::state::{self, JudgeState};
+use futures_util::future::{BoxFuture, FutureExt};
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// Runs the judge on the submission while relaying its progress, and reads the verdict it
+/// writes. The judge is stopped if the controller shuts down, the lease is lost or the judging
+/// times out. A judge that times out or exits without a verdict fails the stage with an error
+/// that is reported as a System Error.
+pub struct RunJudge;
+
+impl Stage for RunJudge {
+    fn name(&self) -> &'static str {
+        "run judge"
+    }
+
+    fn run<'a>(&'a self, judging: &'a mut Judging<'_>) -> BoxFuture<'a, StageResult> {
+        async move {
+            let metadata_path = judging.metadata.clone().ok_or(ControllerError::OutOfOrder(
+                "The workspace has not been prepared.",
+            ))?;
+            judging.report_state(JudgeState::Compiling).await;
+
+            let context = judging.context;
+            let opts = &context.opts;
+            let worker = judging.worker;
+            let publisher = context.publisher.as_ref();
+            let submission_id = judging.job.submission_id;
+            let submission = judging.submission()?;
+            let is_problem_interactive = judging.is_interactive()?;
+            let files = judging.problem_files()?;
+            let source_path = judging.source_path();
+            let verdict_path = judging.verdict_path();
+            let total_testcases = judging.judged.len() as i32;
+            let session = &judging.session;
+            let lease_lost = match &judging.lease {
+                Some(lease) => lease.lost().clone(),
+                None => shutdown::trigger().1,
+            };
+
+            let problem_lock = context.locks.get(&submission.problem_slug);
+            let _guard = problem_lock.read().await;
+
+            let checker = judging.checker()?;
+            let interactor = if is_problem_interactive {
+                Some(judging.interactor()?)
+            } else {
+                None
+            };
+
+            // Start the judging process.
+            let sandboxes_count_str = worker.sandboxes.to_string();
+            let sandbox_offset_str = worker.sandbox_offset.to_string();
+            let mut args: Vec<&str> = vec![
+                "--metadata",
+                metadata_path.to_str().unwrap(),
+                "--language",
+                &submission.language,
+                "--source",
+                source_path.to_str().unwrap(),
+                "--checker",
+                checker.source.to_str().unwrap(),
+                "--testcases",
+                files.testcases.to_str().unwrap(),
+                "--testlib",
+                files.testlib.to_str().unwrap(),
+                "--sandboxes",
+                &sandboxes_count_str,
+                "--checker-language",
+                &checker.language,
+                "--languages-definition",
+                &opts.language_definition,
+                "--verdict",
+                verdict_path.to_str().unwrap(),
+                "--verdict-format",
+                "json",
+                "-vv",
+            ];
+
+            // The judge starts at the first sandbox unless told otherwise. The flag is checked at
+            // startup to be supported if there is more than one worker.
+            if worker.sandbox_offset > 0 {
+                args.push(SANDBOX_OFFSET_FLAG);
+                args.push(&sandbox_offset_str);
+            }
+
+            if let Some(socket) = &worker.socket {
+                args.push("--socket");
+                args.push(socket);
+            }
+
+            // Prebuilt executables spare the judge from compiling the programs for every
+            // submission.
+            if let (Some(flag), Some(executable)) = (checker.executable_flag, &checker.executable) {
+                args.push(flag);
+                args.push(executable.to_str().unwrap());
+            }
+
+            if let Some(interactor) = &interactor {
+                args.push("--interactor");
+                args.push(interactor.source.to_str().unwrap());
+                if context.judge_flags.supports(INTERACTOR_LANGUAGE_FLAG) {
+                    args.push(INTERACTOR_LANGUAGE_FLAG);
+                    args.push(&interactor.language);
+                } else if interactor.language != checker.language {
+                    return Err(ControllerError::Metadata(format!(
+                        "The interactor is written in {}, but the judge does not support {} to \
+                         compile it in another language than the checker.",
+                        interactor.language, INTERACTOR_LANGUAGE_FLAG
+                    )));
+                }
+                if let (Some(flag), Some(executable)) =
+                    (interactor.executable_flag, &interactor.executable)
+                {
+                    args.push(flag);
+                    args.push(executable.to_str().unwrap());
+                }
+            }
+
+            // Launch TCP listening server
+            let socket = worker.socket.clone();
+            let listener_stop = Arc::new(AtomicBool::new(false));
+            let tcp_listener_thread = if let Some(socket) = socket {
+                let socket = socket.clone();
+                let listener_stop = listener_stop.clone();
+                let session = session.clone();
+                let publisher = publisher.cloned();
+                let push_http_results = !opts.disable_http_results;
+
+                log::debug!("Spawning TCP listener thread...");
+
+                Some(thread::spawn(move || {
+                    let context = zmq::Context::new();
+                    let requester = context.socket(zmq::SUB).unwrap();
+
+                    requester
+                        .connect(&socket)
+                        .expect("Failed to connect to socket.");
+                    requester
+                        .set_subscribe(b"")
+                        .expect("Failed to set subscription.");
+                    // Wake up regularly to check whether the judge has exited without notice.
+                    requester
+                        .set_rcvtimeo(500)
+                        .expect("Failed to set receive timeout.");
+
+                    let mut judged_testcases: i32 = 0;
+                    let mut prev_request_instant = std::time::Instant::now();
+
+                    let mut msg = zmq::Message::new();
+                    loop {
+                        if requester.recv(&mut msg, 0).is_err() {
+                            if listener_stop.load(Ordering::SeqCst) {
+                                break;
+                            }
+                            continue;
+                        }
+                        println!("Received message: {}", msg.as_str().unwrap_or(""));
+
+                        let value: serde_json::Value =
+                            match msg.as_str().and_then(|msg| serde_json::from_str(msg).ok()) {
+                                Some(value) => value,
+                                None => {
+                                    log::warn!("Ignoring malformed message from the judge.");
+                                    continue;
+                                }
+                            };
+                        let event_type = value["event_type"].as_str().unwrap_or("");
+
+                        if event_type == "testcase" {
+                            // One submission received.
+                            judged_testcases += 1;
+
+                            if judged_testcases == 1 {
+                                // The submission has been compiled once the first testcase has
+                                // been judged.
+                                let mut session = session.clone();
+                                let client = reqwest::Client::new();
+                                let publisher = publisher.clone();
+                                let mut rt = tokio::runtime::Runtime::new().unwrap();
+                                let local = tokio::task::LocalSet::new();
+                                local.block_on(&mut rt, async move {
+                                    state::report(
+                                        &client,
+                                        &mut session,
+                                        publisher.as_ref(),
+                                        push_http_results,
+                                        submission_id,
+                                        JudgeState::Running,
+                                    )
+                                    .await;
+                                });
+                            }
+
+                            // TODO set cooldown (e.g. 1s) for status update
+                            let mut session = session.clone();
+                            let client = reqwest::Client::new();
+                            let publisher = publisher.clone();
+
+                            if judged_testcases < total_testcases
+                                && prev_request_instant.elapsed()
+                                    > std::time::Duration::from_secs(1)
+                            {
+                                prev_request_instant = std::time::Instant::now();
+
+                                let mut rt = tokio::runtime::Runtime::new().unwrap();
+                                let local = tokio::task::LocalSet::new();
+                                local.block_on(&mut rt, async move {
+                                    if let Some(publisher) = publisher {
+                                        if let Err(err) = publisher
+                                            .publish_progress(
+                                                submission_id,
+                                                judged_testcases,
+                                                total_testcases,
+                                            )
+                                            .await
+                                        {
+                                            log::warn!("Failed to publish progress: {}", err);
+                                        }
+                                    }
+
+                                    if push_http_results {
+                                        let access_token = match session.get_access_token().await {
+                                            Ok(access_token) => access_token.to_string(),
+                                            Err(err) => {
+                                                log::warn!("Failed to push progress: {}", err);
+                                                return;
+                                            }
+                                        };
+                                        let _response = client
+                                            .put(session.resolve(vec![
+                                                "submission/",
+                                                &format!("{}/", submission_id),
+                                                "judge/progress",
+                                            ]))
+                                            .bearer_auth(access_token)
+                                            .json(&json!({
+                                                "progress": judged_testcases,
+                                                "total": total_testcases,
+                                            }))
+                                            .send()
+                                            .await;
+                                    }
+                                });
+                            }
+                        }
+
+                        if event_type == "submission" {
+                            // The judging is completed and the thread should terminate.
+                            break;
+                        }
+                    }
+
+                    ()
+                }))
+            } else {
+                None
+            };
+
+            log::info!("Start judging with arguments: {}", args.join(" "));
+            let mut child = JudgeProcess::spawn(&opts.judge, &args)
+                .map_err(ControllerError::io("Failed to start the judge"))?;
+            // Without the socket it cannot be told when the judge has compiled the submission,
+            // so the submission is reported as running right away.
+            if worker.socket.is_none() {
+                judging.report_state(JudgeState::Running).await;
+            }
+
+            let timeout = match opts.judge_timeout {
+                0 => None,
+                timeout => Some(std::time::Duration::from_secs(timeout)),
+            };
+            let exit = child
+                .wait(&[&context.abort, &lease_lost], timeout)
+                .await
+                .map_err(ControllerError::io("Failed to wait for the judge"))?;
+            listener_stop.store(true, Ordering::SeqCst);
+            if let Some(thread) = tcp_listener_thread {
+                if thread.join().is_err() {
+                    log::error!(
+                        "Progress listener of submission {} panicked.",
+                        submission_id
+                    );
+                }
+            }
+
+            let clean = || {
+                worker
+                    .clean()
+                    .map_err(ControllerError::io("Failed to clean the worker folder"))
+            };
+            let verdict: judge_definitions::JudgeOutput = match exit {
+                JudgeExit::Aborted => {
+                    log::warn!("Judging of submission {} aborted.", submission_id);
+                    clean()?;
+                    if let (true, Some(lease)) = (lease_lost.is_fired(), &judging.lease) {
+                        return Err(lease.lost_error());
+                    }
+                    return Err(ControllerError::Aborted);
+                }
+                JudgeExit::TimedOut => {
+                    clean()?;
+                    return Err(ControllerError::JudgeTimeout(opts.judge_timeout));
+                }
+                JudgeExit::Exited(_) if verdict_path.exists() => {
+                    let verdict = std::fs::read_to_string(&verdict_path)
+                        .map_err(ControllerError::io("Failed to read the verdict"))?;
+                    serde_json::from_str(&verdict).map_err(ControllerError::VerdictParse)?
+                }
+                JudgeExit::Exited(status) => {
+                    return Err(ControllerError::JudgeCrash(format!(
+                        "Judge exited with {} without a verdict.",
+                        status
+                    )));
+                }
+            };
+
+            log::info!("Verdict: {}", verdict.verdict);
+            judging.verdict = Some(verdict);
+
+            Ok(())
+        }
+        .boxed()
+    }
+}
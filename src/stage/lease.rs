@@ -0,0 +1,36 @@
+use super::{Judging, Stage, StageResult};
+use crate::lease::Lease;
+use crate::state::JudgeState;
+use futures_util::future::{BoxFuture, FutureExt};
+
+/// Acquires the lease on the submission, so that no other controller judges it at the same time,
+/// and reports the submission as queued. The lease is released by the controller once every
+/// stage has run.
+pub struct AcquireLease;
+
+impl Stage for AcquireLease {
+    fn name(&self) -> &'static str {
+        "acquire lease"
+    }
+
+    fn run<'a>(&'a self, judging: &'a mut Judging<'_>) -> BoxFuture<'a, StageResult> {
+        async move {
+            let opts = &judging.context.opts;
+            if !opts.no_lease {
+                let lease = Lease::acquire(
+                    &judging.client,
+                    &mut judging.session,
+                    &judging.context.registry.name,
+                    judging.job.submission_id,
+                    opts.lease_duration,
+                )
+                .await?;
+                judging.lease = Some(lease);
+            }
+
+            judging.report_state(JudgeState::Queued).await;
+            Ok(())
+        }
+        .boxed()
+    }
+}
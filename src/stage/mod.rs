@@ -0,0 +1,453 @@
+mod judge;
+mod lease;
+mod problem;
+mod resources;
+mod score;
+mod submission;
+mod verdict;
+mod workspace;
+
+use crate::api::{PartialSubmission, ProblemMetadata};
+use crate::cache::ProblemClaim;
+use crate::compile;
+use crate::controller::Context;
+use crate::error::ControllerError;
+use crate::job::Job;
+use crate::language;
+use crate::lease::Lease;
+use crate::problem::ProgramSettings;
+use crate::process::{CHECKER_EXECUTABLE_FLAG, INTERACTOR_EXECUTABLE_FLAG};
+use crate::scoring::ScoreBreakdown;
+use crate::session::Session;
+use crate::state::{self, JudgeState};
+use crate::worker::Worker;
+use futures_util::future::BoxFuture;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub use self::judge::RunJudge;
+pub use self::lease::AcquireLease;
+pub use self::problem::ResolveProblem;
+pub use self::resources::SyncResources;
+pub use self::score::ScoreVerdict;
+pub use self::submission::FetchSubmission;
+pub use self::verdict::PublishVerdict;
+pub use self::workspace::PrepareWorkspace;
+
+/// The names the checker and the interactor are stored under, without extension.
+pub const CHECKER: &str = "checker";
+pub const INTERACTOR: &str = "interactor";
+
+pub type StageResult = Result<(), ControllerError>;
+
+/// One step of judging a submission. Stages are run in order on the same `Judging`, each one
+/// picking up what the previous stages have filled in.
+pub trait Stage: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn run<'a>(&'a self, judging: &'a mut Judging<'_>) -> BoxFuture<'a, StageResult>;
+}
+
+/// The stages a submission goes through, in order.
+pub fn pipeline() -> Vec<Box<dyn Stage>> {
+    vec![
+        Box::new(AcquireLease),
+        Box::new(FetchSubmission),
+        Box::new(ResolveProblem),
+        Box::new(SyncResources),
+        Box::new(PrepareWorkspace),
+        Box::new(RunJudge),
+        Box::new(ScoreVerdict),
+        Box::new(PublishVerdict),
+    ]
+}
+
+/// The error of a stage, naming the stage it occurred in.
+#[derive(Debug)]
+pub struct StageError {
+    pub stage: &'static str,
+    pub error: ControllerError,
+}
+
+/// A submission on its way through the stages.
+pub struct Judging<'a> {
+    pub context: &'a Context,
+    pub worker: &'a Worker,
+    pub job: &'a Job,
+    pub client: reqwest::Client,
+    pub session: Session,
+    pub lease: Option<Lease>,
+    /// The claim on the problem, which keeps it in the cache while the submission is judged.
+    pub claim: Option<ProblemClaim>,
+    pub submission: Option<PartialSubmission>,
+    pub problem: Option<ProblemMetadata>,
+    /// The metadata passed to the judge.
+    pub metadata: Option<PathBuf>,
+    /// The indices of the testcases listed in the metadata passed to the judge.
+    pub judged: Vec<usize>,
+    pub verdict: Option<judge_definitions::JudgeOutput>,
+    pub score: Option<ScoreBreakdown>,
+    /// How long each stage that has run took.
+    pub timings: Vec<(&'static str, Duration)>,
+}
+
+/// The files of a problem in the resource folder. The checker and the interactor are stored with
+/// the extension of their language.
+pub struct ProblemFiles {
+    pub folder: PathBuf,
+    pub testcases: PathBuf,
+    pub metadata: PathBuf,
+    pub testlib: PathBuf,
+}
+
+/// The checker or the interactor of a problem, as passed to the judge.
+pub struct Program {
+    pub source: PathBuf,
+    pub language: String,
+    /// The flag the judge is passed the precompiled executable with, if the judge supports it.
+    pub executable_flag: Option<&'static str>,
+    /// The precompiled executable of the program, if it has been compiled and the judge can run
+    /// it.
+    pub executable: Option<PathBuf>,
+}
+
+impl<'a> Judging<'a> {
+    pub fn new(
+        context: &'a Context,
+        worker: &'a Worker,
+        job: &'a Job,
+        session: Session,
+    ) -> Judging<'a> {
+        Judging {
+            context,
+            worker,
+            job,
+            client: reqwest::Client::new(),
+            session,
+            lease: None,
+            claim: None,
+            submission: None,
+            problem: None,
+            metadata: None,
+            judged: vec![],
+            verdict: None,
+            score: None,
+            timings: vec![],
+        }
+    }
+
+    pub fn submission(&self) -> Result<&PartialSubmission, ControllerError> {
+        self.submission.as_ref().ok_or(ControllerError::OutOfOrder(
+            "The submission has not been fetched.",
+        ))
+    }
+
+    pub fn problem(&self) -> Result<&ProblemMetadata, ControllerError> {
+        self.problem.as_ref().ok_or(ControllerError::OutOfOrder(
+            "The problem has not been resolved.",
+        ))
+    }
+
+    pub fn verdict(&self) -> Result<&judge_definitions::JudgeOutput, ControllerError> {
+        self.verdict
+            .as_ref()
+            .ok_or(ControllerError::OutOfOrder("The judge has not run."))
+    }
+
+    pub fn is_interactive(&self) -> Result<bool, ControllerError> {
+        Ok(self.problem()?.is_interactive())
+    }
+
+    pub fn problem_files(&self) -> Result<ProblemFiles, ControllerError> {
+        Ok(ProblemFiles::new(
+            &self.context.opts.folder,
+            &self.submission()?.problem_slug,
+        ))
+    }
+
+    /// The stored checker of the problem.
+    pub fn checker(&self) -> Result<Program, ControllerError> {
+        let source = self.problem_files()?.checker();
+        self.program(
+            CHECKER,
+            CHECKER_EXECUTABLE_FLAG,
+            source,
+            &self.problem()?.checker,
+        )
+    }
+
+    /// The stored interactor of the problem.
+    pub fn interactor(&self) -> Result<Program, ControllerError> {
+        let source = self.problem_files()?.interactor();
+        self.program(
+            INTERACTOR,
+            INTERACTOR_EXECUTABLE_FLAG,
+            source,
+            &self.problem()?.interactor,
+        )
+    }
+
+    fn program(
+        &self,
+        name: &str,
+        executable_flag: &'static str,
+        source: Option<PathBuf>,
+        settings: &ProgramSettings,
+    ) -> Result<Program, ControllerError> {
+        let problem_slug = &self.submission()?.problem_slug;
+        let source = source.ok_or_else(|| {
+            ControllerError::Cache(format!(
+                "The {} of problem {} is missing.",
+                name, problem_slug
+            ))
+        })?;
+        let language = language::resolve(
+            settings.language.as_deref(),
+            &source,
+            &self.context.capabilities,
+            &self.context.opts.checker_language,
+        )?;
+        let executable_flag =
+            Some(executable_flag).filter(|flag| self.context.judge_flags.supports(flag));
+        let executable = Some(compile::executable_path(&source, &language))
+            .filter(|executable| executable_flag.is_some() && executable.exists());
+
+        Ok(Program {
+            source,
+            language,
+            executable_flag,
+            executable,
+        })
+    }
+
+    /// Report the state of the submission to the server.
+    pub async fn report_state(&mut self, state: JudgeState) {
+        state::report(
+            &self.client,
+            &mut self.session,
+            self.context.publisher.as_ref(),
+            !self.context.opts.disable_http_results,
+            self.job.submission_id,
+            state,
+        )
+        .await;
+    }
+
+    pub fn source_path(&self) -> PathBuf {
+        self.worker.temp.join("source")
+    }
+
+    pub fn verdict_path(&self) -> PathBuf {
+        self.worker.temp.join("verdict.json")
+    }
+}
+
+impl ProblemFiles {
+    pub fn new(folder: &str, problem_slug: &str) -> ProblemFiles {
+        ProblemFiles::at(Path::new(folder).join(problem_slug))
+    }
+
+    fn at(folder: PathBuf) -> ProblemFiles {
+        ProblemFiles {
+            testcases: folder.join("testcases"),
+            metadata: folder.join("metadata.yml"),
+            testlib: folder.join("testlib.h"),
+            folder,
+        }
+    }
+
+    /// The files of the folder the problem is downloaded into before it replaces this folder.
+    pub fn staging(&self) -> ProblemFiles {
+        ProblemFiles::at(self.sibling("staging"))
+    }
+
+    /// The folder this folder is moved to while it is being replaced.
+    pub fn outdated(&self) -> PathBuf {
+        self.sibling("outdated")
+    }
+
+    fn sibling(&self, suffix: &str) -> PathBuf {
+        let name = self
+            .folder
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("problem");
+        self.folder.with_file_name(format!(".{}.{}", name, suffix))
+    }
+
+    /// Check that every file needed to judge the problem is present.
+    pub fn validate(&self, is_interactive: bool) -> Result<(), String> {
+        let missing =
+            |file: &str| format!("The {} is missing from {}.", file, self.folder.display());
+        if !self.metadata.is_file() {
+            return Err(missing("metadata"));
+        }
+        if !self.testlib.is_file() {
+            return Err(missing("testlib.h"));
+        }
+        if !self.testcases.is_dir() {
+            return Err(missing("testcases folder"));
+        }
+        if self.checker().is_none() {
+            return Err(missing("checker"));
+        }
+        if is_interactive && self.interactor().is_none() {
+            return Err(missing("interactor"));
+        }
+        Ok(())
+    }
+
+    /// The stored checker, if any.
+    pub fn checker(&self) -> Option<PathBuf> {
+        language::find_program(&self.folder, CHECKER)
+    }
+
+    /// The stored interactor, if any.
+    pub fn interactor(&self) -> Option<PathBuf> {
+        language::find_program(&self.folder, INTERACTOR)
+    }
+}
+
+impl std::fmt::Display for StageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Failed to {}: {}", self.stage, self.error)
+    }
+}
+
+impl std::error::Error for StageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const METADATA: &str = "type: standard
+last_update: 2020-01-01T00:00:00Z
+testcases:
+  - input: 1.in
+    output: 1.out
+  - input: 2.in
+    output: 2.out
+  - input: 3.in
+    output: 3.out
+";
+
+    fn submission() -> PartialSubmission {
+        PartialSubmission {
+            id: 1,
+            problem_slug: "a-plus-b".to_string(),
+            language: "cpp".to_string(),
+            source_code: "int main() {}".to_string(),
+        }
+    }
+
+    /// A judging of a submission whose problem has been resolved and stored in the cache.
+    fn resolved<'a>(context: &'a Context, worker: &'a Worker, job: &'a Job) -> Judging<'a> {
+        let mut judging = Judging::new(context, worker, job, Session::new(&context.opts.server));
+        judging.submission = Some(submission());
+        judging.problem = Some(serde_yaml::from_str(METADATA).unwrap());
+        let files = judging.problem_files().unwrap();
+        std::fs::create_dir_all(&files.folder).unwrap();
+        std::fs::write(&files.metadata, METADATA).unwrap();
+        std::fs::create_dir_all(&worker.temp).unwrap();
+        judging
+    }
+
+    #[tokio::test]
+    async fn prepare_workspace_writes_the_source_and_judges_every_testcase() {
+        let context = Context::for_tests(&[]);
+        let worker = Worker::new(&context.opts, 0);
+        let job = Job::new(1);
+        let mut judging = resolved(&context, &worker, &job);
+        std::fs::write(judging.verdict_path(), "{}").unwrap();
+
+        PrepareWorkspace.run(&mut judging).await.unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(judging.source_path()).unwrap(),
+            "int main() {}"
+        );
+        assert!(!judging.verdict_path().exists());
+        assert_eq!(
+            judging.metadata,
+            Some(judging.problem_files().unwrap().metadata)
+        );
+        assert_eq!(judging.judged, vec![0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn prepare_workspace_passes_only_the_requested_testcases() {
+        let context = Context::for_tests(&[]);
+        let worker = Worker::new(&context.opts, 0);
+        let job = Job {
+            testcases: Some(vec![0, 2]),
+            ..Job::new(1)
+        };
+        let mut judging = resolved(&context, &worker, &job);
+
+        PrepareWorkspace.run(&mut judging).await.unwrap();
+
+        assert_eq!(judging.judged, vec![0, 2]);
+        let metadata = judging.metadata.clone().unwrap();
+        assert_eq!(metadata, worker.temp.join("metadata.yml"));
+        let metadata: ProblemMetadata =
+            serde_yaml::from_str(&std::fs::read_to_string(metadata).unwrap()).unwrap();
+        let inputs: Vec<_> = metadata
+            .testcases
+            .iter()
+            .map(|testcase| testcase.input.clone().unwrap())
+            .collect();
+        assert_eq!(inputs, vec!["1.in", "3.in"]);
+    }
+
+    #[tokio::test]
+    async fn score_verdict_leaves_problems_without_groups_unscored() {
+        let context = Context::for_tests(&[]);
+        let worker = Worker::new(&context.opts, 0);
+        let job = Job::new(1);
+        let mut judging = resolved(&context, &worker, &job);
+        judging.judged = vec![0, 1, 2];
+        judging.verdict = Some(
+            serde_json::from_value(json!({
+                "verdict": "AC",
+                "compile_message": "",
+                "time": 0.1,
+                "memory": 1024,
+                "testcases": [],
+            }))
+            .unwrap(),
+        );
+
+        ScoreVerdict.run(&mut judging).await.unwrap();
+
+        assert!(judging.score.is_none());
+    }
+
+    #[tokio::test]
+    async fn stages_run_out_of_order_fail_instead_of_panicking() {
+        // Keep the stages from reporting the state of the submission to the server.
+        let context = Context::for_tests(&["--disable-http-results"]);
+        let worker = Worker::new(&context.opts, 0);
+        let job = Job::new(1);
+        let mut judging = Judging::new(&context, &worker, &job, Session::new(&context.opts.server));
+
+        for stage in pipeline().iter().skip(3) {
+            let result = stage.run(&mut judging).await;
+            assert!(
+                matches!(result, Err(ControllerError::OutOfOrder(_))),
+                "{} ran without the earlier stages",
+                stage.name()
+            );
+        }
+
+        let mut judging = resolved(&context, &worker, &job);
+        let result = RunJudge.run(&mut judging).await;
+        assert!(matches!(result, Err(ControllerError::OutOfOrder(_))));
+        let result = ScoreVerdict.run(&mut judging).await;
+        assert!(matches!(result, Err(ControllerError::OutOfOrder(_))));
+    }
+}
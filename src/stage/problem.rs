@@ -0,0 +1,59 @@
+use super::{Judging, Stage, StageResult};
+use crate::api::*;
+use crate::error::ControllerError;
+use futures_util::future::{BoxFuture, FutureExt};
+
+/// Fetches the problem of the submission from the judge server, and checks that its metadata is
+/// valid. With `--capability-routing`, it is also checked that this host can judge it.
+pub struct ResolveProblem;
+
+impl Stage for ResolveProblem {
+    fn name(&self) -> &'static str {
+        "resolve problem"
+    }
+
+    fn run<'a>(&'a self, judging: &'a mut Judging<'_>) -> BoxFuture<'a, StageResult> {
+        async move {
+            let problem_slug = judging.submission()?.problem_slug.clone();
+            let session = &mut judging.session;
+
+            log::info!("Getting problem...");
+            let context = format!("Failed to get problem {}", problem_slug);
+            let problem = judging
+                .client
+                .get(session.resolve(vec!["problem/", &problem_slug]))
+                .bearer_auth(session.get_access_token().await?)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(ControllerError::network(&context))?
+                .json::<ApiSuccess<serde_json::Value>>()
+                .await
+                .map_err(ControllerError::network(&context))?
+                .data;
+            // Parse the metadata separately, so that malformed metadata is reported rather than
+            // retried like a failed request.
+            let problem: ProblemMetadata = serde_json::from_value(problem)
+                .map_err(|err| ControllerError::Metadata(err.to_string()))?;
+            log::debug!("Problem: {:?}", problem);
+            problem.validate().map_err(ControllerError::Metadata)?;
+
+            // Give the job back if it has been routed to a host which cannot judge it.
+            if judging.context.opts.capability_routing {
+                judging
+                    .context
+                    .capabilities
+                    .check(
+                        &judging.submission()?.language,
+                        problem.is_interactive(),
+                        judging.job.memory_limit.or(problem.memory_limit),
+                    )
+                    .map_err(ControllerError::Unsupported)?;
+            }
+            judging.problem = Some(problem);
+
+            Ok(())
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,365 @@
+use super::{Judging, ProblemFiles, Program, Stage, StageResult, CHECKER, INTERACTOR};
+use crate::cache::{self, CacheManifest};
+use crate::compile::{self, Compilers};
+use crate::error::ControllerError;
+use crate::language;
+use crate::net::*;
+use crate::session::Session;
+use crate::state::{self, JudgeState};
+use crate::util::copy_recursively;
+use futures_util::future::{BoxFuture, FutureExt};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Downloads the files of the problem into the resource folder, unless they are up to date. The
+/// cache is checked against the version of the problem given by the server, and resources the
+/// server reports as unchanged by their ETag are reused.
+pub struct SyncResources;
+
+impl Stage for SyncResources {
+    fn name(&self) -> &'static str {
+        "sync resources"
+    }
+
+    fn run<'a>(&'a self, judging: &'a mut Judging<'_>) -> BoxFuture<'a, StageResult> {
+        async move {
+            let problem_slug = judging.submission()?.problem_slug.clone();
+            let version = judging.problem()?.resource_version().to_string();
+            let is_problem_interactive = judging.is_interactive()?;
+            let files = judging.problem_files()?;
+            let testcases_zip_path = judging.worker.temp.join("testcases.zip");
+            let context = judging.context;
+
+            // Keep the problem from being evicted from the cache until the submission is judged.
+            judging.claim = Some(context.locks.claim(&problem_slug));
+            let record_use = || {
+                cache::touch(&files.folder).map_err(|err| {
+                    ControllerError::Cache(format!(
+                        "Failed to record use of problem {}: {}",
+                        problem_slug, err
+                    ))
+                })
+            };
+
+            // Most submissions find the problem cached and compiled, for which the read lock is
+            // enough. The write lock is only taken if the problem has to be downloaded or
+            // compiled, as it waits for all submissions of the problem being judged.
+            let problem_lock = context.locks.get(&problem_slug);
+            {
+                let _guard = problem_lock.read().await;
+                let is_cached = CacheManifest::load(&files.folder)
+                    .map_or(false, |cached| cached.version == version);
+                if is_cached && is_compiled(judging)? {
+                    record_use()?;
+                    return Ok(());
+                }
+            }
+
+            let client = &judging.client;
+            let session = &mut judging.session;
+
+            let problem_base_url =
+                session.resolve(vec!["problem/", &format!("{}/", &problem_slug)]);
+            let metadata_url = problem_base_url
+                .join("metadata")
+                .expect("Invalid URL fragment.");
+            let testcases_url = problem_base_url
+                .join("testcases")
+                .expect("Invalid URL fragment.");
+            let checker_url = problem_base_url
+                .join("checker")
+                .expect("Invalid URL fragment.");
+            let interactor_url = problem_base_url
+                .join("interactor")
+                .expect("Invalid URL fragment.");
+            let testlib_url = session.resolve_single("admin/testlib");
+
+            // Another submission may have synced the problem while this one waited for the lock.
+            let download_guard = problem_lock.write().await;
+            let cached = CacheManifest::load(&files.folder);
+            let should_download = match &cached {
+                Ok(cached) if cached.version == version => false,
+                Ok(cached) => {
+                    log::info!(
+                        "Problem {} has been updated from version {} to {}.",
+                        problem_slug,
+                        cached.version,
+                        version
+                    );
+                    true
+                }
+                // A cache without a valid manifest is incomplete and downloaded again.
+                Err(err) => {
+                    if files.folder.exists() {
+                        log::warn!("Cache of problem {} is invalid: {}", problem_slug, err);
+                    }
+                    true
+                }
+            };
+
+            if should_download {
+                state::report(
+                    client,
+                    session,
+                    context.publisher.as_ref(),
+                    !context.opts.disable_http_results,
+                    judging.job.submission_id,
+                    JudgeState::Downloading,
+                )
+                .await;
+
+                let cache_error = |err: std::io::Error| {
+                    ControllerError::Cache(format!(
+                        "Failed to store problem {}: {}",
+                        problem_slug, err
+                    ))
+                };
+
+                // Download into a staging folder which only replaces the cached problem once it
+                // is complete, so that a failed download never leaves a broken cache behind. A
+                // staging folder left over by an earlier failure is discarded.
+                let staging = files.staging();
+                if staging.folder.exists() {
+                    std::fs::remove_dir_all(&staging.folder).map_err(cache_error)?;
+                }
+                std::fs::create_dir_all(&staging.folder).map_err(cache_error)?;
+                let mut fetcher = Fetcher {
+                    client,
+                    session,
+                    cached: cached.ok(),
+                    etags: BTreeMap::new(),
+                };
+
+                // Download metadata, checker and testlib.h. The checker and the interactor are
+                // stored with the extension of their language.
+                fetcher
+                    .fetch(
+                        "metadata",
+                        metadata_url,
+                        Some((files.metadata.as_path(), staging.metadata.as_path())),
+                        &staging.metadata,
+                    )
+                    .await?;
+                fetcher
+                    .fetch_program(CHECKER, checker_url, &files, &staging)
+                    .await?;
+                fetcher
+                    .fetch(
+                        "testlib",
+                        testlib_url,
+                        Some((files.testlib.as_path(), staging.testlib.as_path())),
+                        &staging.testlib,
+                    )
+                    .await?;
+                if is_problem_interactive {
+                    fetcher
+                        .fetch_program(INTERACTOR, interactor_url, &files, &staging)
+                        .await?;
+                }
+
+                // Download testcases
+                let downloaded = fetcher
+                    .fetch(
+                        "testcases",
+                        testcases_url,
+                        Some((files.testcases.as_path(), staging.testcases.as_path())),
+                        &testcases_zip_path,
+                    )
+                    .await?;
+                if downloaded.is_some() {
+                    log::info!(
+                        "Compressed testcases for problem {} downloaded. Extracting...",
+                        &problem_slug
+                    );
+
+                    crate::util::unzip(&testcases_zip_path, &staging.testcases).await?;
+                    std::fs::remove_file(&testcases_zip_path).map_err(cache_error)?;
+                    log::info!("Extracted testcases.");
+                }
+
+                staging
+                    .validate(is_problem_interactive)
+                    .map_err(ControllerError::Cache)?;
+                // The manifest is written last, as it marks the cache as complete.
+                let manifest = CacheManifest {
+                    version,
+                    etags: fetcher.etags,
+                };
+                manifest.store(&staging.folder).map_err(cache_error)?;
+
+                // Swap the staging folder in. The write lock keeps workers away from the problem
+                // while it is briefly missing.
+                let outdated = files.outdated();
+                if outdated.exists() {
+                    std::fs::remove_dir_all(&outdated).map_err(cache_error)?;
+                }
+                if files.folder.exists() {
+                    std::fs::rename(&files.folder, &outdated).map_err(cache_error)?;
+                }
+                std::fs::rename(&staging.folder, &files.folder).map_err(cache_error)?;
+                if outdated.exists() {
+                    std::fs::remove_dir_all(&outdated).map_err(cache_error)?;
+                }
+                log::info!("Stored problem {}.", problem_slug);
+            }
+
+            // Compile the checker and the interactor once per version of the problem, so that
+            // the judge can run the prebuilt executables.
+            let mut programs = vec![judging.checker()?];
+            if is_problem_interactive {
+                programs.push(judging.interactor()?);
+            }
+            for program in programs {
+                precompile(&context.compilers, &program).await?;
+            }
+
+            record_use()?;
+            drop(download_guard);
+
+            // The cache has only grown if the problem has been downloaded. The lock has to be
+            // released first, as other problems are locked while being evicted.
+            if let (true, Some(cache_size)) = (should_download, context.opts.cache_size) {
+                cache::enforce_quota(&context.opts.folder, cache_size * cache::MB, &context.locks)
+                    .await;
+            }
+
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Downloads the resources of a problem, reusing the cached copies which the server reports as
+/// unchanged.
+struct Fetcher<'a> {
+    client: &'a reqwest::Client,
+    session: &'a mut Session,
+    /// The manifest of the cached resources, if there are any.
+    cached: Option<CacheManifest>,
+    /// The ETags of the fetched resources.
+    etags: BTreeMap<String, String>,
+}
+
+impl Fetcher<'_> {
+    /// Fetch the resource `name` from `url` into `path`. If `reuse` names a cached copy and its
+    /// destination, the cached copy is copied there instead if the server reports that it is
+    /// unchanged, and `None` is returned. Otherwise the headers of the response are returned.
+    async fn fetch(
+        &mut self,
+        name: &str,
+        url: url::Url,
+        reuse: Option<(&Path, &Path)>,
+        path: &Path,
+    ) -> Result<Option<reqwest::header::HeaderMap>, ControllerError> {
+        let etag = match (&self.cached, reuse) {
+            (Some(cached), Some((from, _))) if from.exists() => cached.etags.get(name).cloned(),
+            _ => None,
+        };
+
+        let headers = download_to_file(
+            self.client,
+            url,
+            path,
+            self.session.get_access_token().await?,
+            etag.as_deref(),
+        )
+        .await?;
+        let etag = match &headers {
+            Some(headers) => headers
+                .get(reqwest::header::ETAG)
+                .and_then(|etag| etag.to_str().ok())
+                .map(String::from),
+            None => {
+                let (from, to) = reuse.expect("Unchanged resource without a cached copy.");
+                copy_recursively(from, to).map_err(|err| {
+                    ControllerError::Cache(format!("Failed to reuse {}: {}", from.display(), err))
+                })?;
+                log::info!("Reused unchanged {}.", from.display());
+                etag
+            }
+        };
+        if let Some(etag) = etag {
+            self.etags.insert(name.to_string(), etag);
+        }
+
+        Ok(headers)
+    }
+
+    /// Fetch the program `name` from `url` into the staging folder, with the extension of its
+    /// language as told by the response.
+    async fn fetch_program(
+        &mut self,
+        name: &str,
+        url: url::Url,
+        files: &ProblemFiles,
+        staging: &ProblemFiles,
+    ) -> Result<(), ControllerError> {
+        let cached = language::find_program(&files.folder, name);
+        let reused = cached.as_ref().and_then(|cached| {
+            cached
+                .file_name()
+                .map(|file_name| staging.folder.join(file_name))
+        });
+        let reuse = match (&cached, &reused) {
+            (Some(cached), Some(reused)) => Some((cached.as_path(), reused.as_path())),
+            _ => None,
+        };
+
+        let download_path = staging.folder.join(format!(".{}.download", name));
+        let headers = match self.fetch(name, url, reuse, &download_path).await? {
+            Some(headers) => headers,
+            None => return Ok(()),
+        };
+        let extension = language::extension_from_headers(&headers)
+            .unwrap_or_else(|| language::DEFAULT_EXTENSION.to_string());
+
+        let path = staging.folder.join(format!("{}.{}", name, extension));
+        std::fs::rename(&download_path, &path)
+            .map_err(ControllerError::io("Failed to store program"))?;
+        log::info!("Downloaded {}.", path.display());
+        Ok(())
+    }
+}
+
+/// Whether the checker and the interactor of the problem need no more compiling.
+fn is_compiled(judging: &Judging) -> Result<bool, ControllerError> {
+    let compilers = &judging.context.compilers;
+    let mut programs = vec![judging.checker()?];
+    if judging.is_interactive()? {
+        programs.push(judging.interactor()?);
+    }
+    Ok(programs
+        .iter()
+        .all(|program| !needs_compiling(compilers, program)))
+}
+
+fn needs_compiling(compilers: &Compilers, program: &Program) -> bool {
+    program.executable_flag.is_some()
+        && program.executable.is_none()
+        && compilers.is_compiled(&program.language)
+}
+
+/// Compile the program unless it has been compiled already, its language is not compiled, or the
+/// judge cannot run prebuilt executables.
+async fn precompile(compilers: &Compilers, program: &Program) -> Result<(), ControllerError> {
+    if !needs_compiling(compilers, program) {
+        return Ok(());
+    }
+
+    let executable = compile::executable_path(&program.source, &program.language);
+    if let Some(folder) = executable.parent() {
+        std::fs::create_dir_all(folder)
+            .map_err(ControllerError::io("Failed to create the compiled folder"))?;
+    }
+    let result = compilers
+        .compile(&program.language, &program.source, &executable)
+        .await;
+    if result.is_err() && executable.exists() {
+        // Never leave a broken executable behind to be picked up by later submissions.
+        let _ = std::fs::remove_file(&executable);
+    }
+    result?;
+
+    log::info!("Compiled {}.", executable.display());
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use super::{Judging, Stage, StageResult};
+use crate::scoring;
+use futures_util::future::{BoxFuture, FutureExt};
+
+/// Aggregates the testcase results into the scores of the testcase groups of the problem.
+pub struct ScoreVerdict;
+
+impl Stage for ScoreVerdict {
+    fn name(&self) -> &'static str {
+        "score verdict"
+    }
+
+    fn run<'a>(&'a self, judging: &'a mut Judging<'_>) -> BoxFuture<'a, StageResult> {
+        async move {
+            let score = scoring::score(judging.problem()?, &judging.judged, judging.verdict()?);
+            if let Some(score) = &score {
+                log::info!("Score: {} / {}", score.score, score.max_score);
+            }
+            judging.score = score;
+
+            Ok(())
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,40 @@
+use super::{Judging, Stage, StageResult};
+use crate::api::*;
+use crate::error::ControllerError;
+use futures_util::future::{BoxFuture, FutureExt};
+
+/// Fetches the submission from the judge server.
+pub struct FetchSubmission;
+
+impl Stage for FetchSubmission {
+    fn name(&self) -> &'static str {
+        "fetch submission"
+    }
+
+    fn run<'a>(&'a self, judging: &'a mut Judging<'_>) -> BoxFuture<'a, StageResult> {
+        async move {
+            let session = &mut judging.session;
+            let submission_id_str = judging.job.submission_id.to_string();
+
+            log::info!("Getting submission...");
+            let context = format!("Failed to get submission {}", submission_id_str);
+            let submission: PartialSubmission = judging
+                .client
+                .get(session.resolve(vec!["submission/", &submission_id_str]))
+                .bearer_auth(session.get_access_token().await?)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(ControllerError::network(&context))?
+                .json::<ApiSuccess<PartialSubmission>>()
+                .await
+                .map_err(ControllerError::network(&context))?
+                .data;
+            log::debug!("Submission: {:?}", submission);
+            judging.submission = Some(submission);
+
+            Ok(())
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,76 @@
+use super::{Judging, Stage, StageResult};
+use crate::error::ControllerError;
+use crate::scoring::ScoredVerdict;
+use crate::state::JudgeState;
+use futures_util::future::{BoxFuture, FutureExt};
+
+/// Publishes the verdict and its score breakdown to the result exchange and pushes them through
+/// the HTTP API. Nothing is published if the lease on the submission has been lost, as another
+/// controller may be judging the submission by now.
+pub struct PublishVerdict;
+
+impl Stage for PublishVerdict {
+    fn name(&self) -> &'static str {
+        "publish verdict"
+    }
+
+    fn run<'a>(&'a self, judging: &'a mut Judging<'_>) -> BoxFuture<'a, StageResult> {
+        async move {
+            let submission_id = judging.job.submission_id;
+            if let Some(lease) = &judging.lease {
+                if lease.lost().is_fired() {
+                    return Err(lease.lost_error());
+                }
+            }
+
+            judging.report_state(JudgeState::Finalizing).await;
+
+            let opts = &judging.context.opts;
+            let verdict = ScoredVerdict {
+                verdict: judging
+                    .verdict
+                    .as_ref()
+                    .ok_or(ControllerError::OutOfOrder("The judge has not run."))?,
+                score: judging.score.as_ref(),
+            };
+            let session = &mut judging.session;
+
+            if let Some(publisher) = &judging.context.publisher {
+                match publisher.publish_verdict(submission_id, &verdict).await {
+                    Ok(()) => log::info!("Published verdict to result exchange."),
+                    // The verdict would be lost if it is not pushed through the HTTP API either.
+                    Err(err) if opts.disable_http_results => return Err(err),
+                    Err(err) => log::warn!("Failed to publish verdict: {}", err),
+                }
+            }
+
+            if !opts.disable_http_results {
+                let response = judging
+                    .client
+                    .put(session.resolve(vec![
+                        "submission/",
+                        &format!("{}/", submission_id),
+                        "judge",
+                    ]))
+                    .bearer_auth(session.get_access_token().await?)
+                    .json(&verdict)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(ControllerError::network("Failed to push the verdict"))?
+                    .text()
+                    .await
+                    .map_err(ControllerError::network("Failed to push the verdict"))?;
+
+                log::info!(
+                    "Push to {}, response: {}",
+                    session.resolve(vec!["submission/", &format!("{}/", submission_id), "judge"]),
+                    response
+                );
+            }
+
+            Ok(())
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,56 @@
+use super::{Judging, Stage, StageResult};
+use crate::error::ControllerError;
+use futures_util::future::{BoxFuture, FutureExt};
+
+/// Writes the source code into the worker's temporary folder, removes the verdict of the
+/// previous submission and chooses the metadata to pass to the judge.
+pub struct PrepareWorkspace;
+
+impl Stage for PrepareWorkspace {
+    fn name(&self) -> &'static str {
+        "prepare workspace"
+    }
+
+    fn run<'a>(&'a self, judging: &'a mut Judging<'_>) -> BoxFuture<'a, StageResult> {
+        async move {
+            std::fs::write(judging.source_path(), &judging.submission()?.source_code)
+                .map_err(ControllerError::io("Failed to write the source code"))?;
+
+            let verdict_path = judging.verdict_path();
+            if verdict_path.exists() {
+                std::fs::remove_file(&verdict_path)
+                    .map_err(ControllerError::io("Failed to remove the previous verdict"))?;
+            }
+
+            let metadata_path = judging.problem_files()?.metadata;
+            let problem_lock = judging
+                .context
+                .locks
+                .get(&judging.submission()?.problem_slug);
+            let _guard = problem_lock.read().await;
+
+            // Only judge the requested testcases by passing a filtered copy of the metadata.
+            let testcase_count = judging.problem()?.testcases.len();
+            let (metadata_path, judged) = match &judging.job.testcases {
+                Some(testcases) => {
+                    let filtered_metadata_path = judging.worker.temp.join("metadata.yml");
+                    crate::util::filter_testcases(
+                        &metadata_path,
+                        &filtered_metadata_path,
+                        testcases,
+                    )?;
+                    let judged = (0..testcase_count)
+                        .filter(|index| testcases.contains(index))
+                        .collect();
+                    (filtered_metadata_path, judged)
+                }
+                None => (metadata_path, (0..testcase_count).collect()),
+            };
+            judging.metadata = Some(metadata_path);
+            judging.judged = judged;
+
+            Ok(())
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,65 @@
+use crate::publisher::ResultPublisher;
+use crate::session::Session;
+use serde::Serialize;
+use serde_json::json;
+
+/// The states a submission goes through on the controller before its verdict is known.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JudgeState {
+    /// The submission has been accepted by the controller.
+    Queued,
+    /// The files of the problem are being downloaded.
+    Downloading,
+    /// The judge has been started and is compiling the submission. Without `--socket` the end of
+    /// the compilation cannot be told, so the submission is reported as running once the judge
+    /// has been started.
+    Compiling,
+    /// The judge is running the testcases.
+    Running,
+    /// The verdict is being reported.
+    Finalizing,
+}
+
+/// Report the state of the submission through the HTTP API and the result exchange. Failures are
+/// only logged, as the state is merely shown to the user.
+pub async fn report(
+    client: &reqwest::Client,
+    session: &mut Session,
+    publisher: Option<&ResultPublisher>,
+    push_http_results: bool,
+    submission_id: i32,
+    state: JudgeState,
+) {
+    log::info!("Submission {} is {:?}.", submission_id, state);
+
+    if let Some(publisher) = publisher {
+        if let Err(err) = publisher.publish_state(submission_id, state).await {
+            log::warn!("Failed to publish state: {}", err);
+        }
+    }
+
+    if push_http_results {
+        let access_token = match session.get_access_token().await {
+            Ok(access_token) => access_token.to_string(),
+            Err(err) => {
+                log::warn!("Failed to push state: {}", err);
+                return;
+            }
+        };
+        let result = client
+            .put(session.resolve(vec![
+                "submission/",
+                &format!("{}/", submission_id),
+                "judge/state",
+            ]))
+            .bearer_auth(access_token)
+            .json(&json!({ "state": state }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(err) = result {
+            log::warn!("Failed to push state: {}", err);
+        }
+    }
+}
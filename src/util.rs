@@ -1,18 +1,22 @@
+use crate::error::ControllerError;
 use futures_util::stream::{Stream, StreamExt};
 use std::io::Write;
 
 pub async fn write_stream_to_file<'a, T>(
     stream: &mut T,
     path: &'a std::path::Path,
-) -> Result<(), Box<dyn std::error::Error>>
+) -> Result<(), ControllerError>
 where
     T: Stream<Item = reqwest::Result<bytes::Bytes>> + std::marker::Unpin,
 {
-    let mut file = std::fs::File::create(path).unwrap();
-    while let Some(Ok(item)) = stream.next().await {
-        file.write(&item)?;
+    let context = format!("Failed to write {}", path.display());
+    let mut file = std::fs::File::create(path).map_err(ControllerError::io(&context))?;
+    while let Some(item) = stream.next().await {
+        let item = item.map_err(ControllerError::network("Failed to receive the file"))?;
+        file.write_all(&item)
+            .map_err(ControllerError::io(&context))?;
     }
-    file.flush()?;
+    file.flush().map_err(ControllerError::io(&context))?;
     Ok(())
 }
 
@@ -20,33 +24,81 @@ where
 pub async fn unzip<'a>(
     zip_path: &'a std::path::Path,
     folder_path: &'a std::path::Path,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), ControllerError> {
     log::info!(
         "Extracting {} to {}...",
-        zip_path.to_str().unwrap(),
-        folder_path.to_str().unwrap()
+        zip_path.display(),
+        folder_path.display()
     );
 
-    let zip_file = std::fs::File::open(zip_path).unwrap();
-    let mut archive = zip::ZipArchive::new(zip_file).unwrap();
+    let cache_error = |err: &dyn std::fmt::Display| {
+        ControllerError::Cache(format!("Failed to extract {}: {}", zip_path.display(), err))
+    };
+
+    let zip_file = std::fs::File::open(zip_path).map_err(|err| cache_error(&err))?;
+    let mut archive = zip::ZipArchive::new(zip_file).map_err(|err| cache_error(&err))?;
 
     for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
+        let mut file = archive.by_index(i).map_err(|err| cache_error(&err))?;
         let filename = file.sanitized_name();
         let target = folder_path.join(&filename);
 
         if filename.ends_with("/") {
-            std::fs::create_dir_all(&target)?;
+            std::fs::create_dir_all(&target).map_err(|err| cache_error(&err))?;
         } else {
             if let Some(p) = target.parent() {
                 if !p.exists() {
-                    std::fs::create_dir_all(&p)?;
+                    std::fs::create_dir_all(&p).map_err(|err| cache_error(&err))?;
                 }
             }
-            let mut sink = std::fs::File::create(&target).unwrap();
-            std::io::copy(&mut file, &mut sink)?;
+            let mut sink = std::fs::File::create(&target).map_err(|err| cache_error(&err))?;
+            std::io::copy(&mut file, &mut sink).map_err(|err| cache_error(&err))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy the file or folder at `from` to `to`, including the contents of folders.
+pub fn copy_recursively(from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()> {
+    if from.is_dir() {
+        std::fs::create_dir_all(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
         }
+    } else {
+        std::fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// Write a copy of the problem metadata at metadata_path to target_path, keeping only the
+/// testcases with the given zero-based indices.
+pub fn filter_testcases<'a>(
+    metadata_path: &'a std::path::Path,
+    target_path: &'a std::path::Path,
+    indices: &[usize],
+) -> Result<(), ControllerError> {
+    let metadata = std::fs::read_to_string(metadata_path)
+        .map_err(ControllerError::io("Failed to read the problem metadata"))?;
+    let mut metadata: serde_yaml::Value = serde_yaml::from_str(&metadata)
+        .map_err(|err| ControllerError::Metadata(err.to_string()))?;
+
+    if let Some(serde_yaml::Value::Sequence(testcases)) = metadata.get_mut("testcases") {
+        let filtered = testcases
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| indices.contains(index))
+            .map(|(_, testcase)| testcase.clone())
+            .collect();
+        *testcases = filtered;
     }
 
+    let metadata = serde_yaml::to_string(&metadata)
+        .map_err(|err| ControllerError::Metadata(err.to_string()))?;
+    std::fs::write(target_path, metadata).map_err(ControllerError::io(
+        "Failed to write the filtered problem metadata",
+    ))?;
     Ok(())
 }
@@ -0,0 +1,119 @@
+use crate::cli::Opts;
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+
+/// A slot in the worker pool. Every worker owns its own temporary workspace and a disjoint slice
+/// of the sandboxes, so that submissions judged concurrently never share files or sandboxes.
+#[derive(Debug, Clone)]
+pub struct Worker {
+    pub id: i32,
+    pub temp: PathBuf,
+    pub sandboxes: i32,
+    pub sandbox_offset: i32,
+    pub socket: Option<String>,
+}
+
+/// The pool of idle workers. A worker is taken out with `acquire` and handed back through a
+/// sender obtained from `releaser` once the submission has been processed.
+pub struct WorkerPool {
+    sender: mpsc::Sender<Worker>,
+    receiver: mpsc::Receiver<Worker>,
+    size: i32,
+    drained: i32,
+}
+
+impl Worker {
+    pub fn new(opts: &Opts, id: i32) -> Worker {
+        let sandboxes = opts.sandboxes / opts.workers;
+
+        Worker {
+            id,
+            temp: PathBuf::from(&opts.temp).join(format!("worker-{}", id)),
+            sandboxes,
+            sandbox_offset: sandboxes * id,
+            socket: opts.socket.as_ref().map(|socket| worker_socket(socket, id)),
+        }
+    }
+
+    /// A worker owning every sandbox, for judging submissions one at a time outside of the pool.
+    pub fn standalone(opts: &Opts) -> Worker {
+        Worker {
+            sandboxes: opts.sandboxes,
+            sandbox_offset: 0,
+            ..Worker::new(opts, 0)
+        }
+    }
+
+    /// Remove everything left behind in the workspace of the worker.
+    pub fn clean(&self) -> std::io::Result<()> {
+        std::fs::remove_dir_all(&self.temp)?;
+        std::fs::create_dir_all(&self.temp)
+    }
+}
+
+impl WorkerPool {
+    pub fn new(opts: &Opts) -> WorkerPool {
+        let (mut sender, receiver) = mpsc::channel(opts.workers as usize);
+
+        for id in 0..opts.workers {
+            sender
+                .try_send(Worker::new(opts, id))
+                .expect("Failed to fill worker pool.");
+        }
+
+        WorkerPool {
+            sender,
+            receiver,
+            size: opts.workers,
+            drained: 0,
+        }
+    }
+
+    /// Wait until a worker is idle and take it out of the pool.
+    pub async fn acquire(&mut self) -> Worker {
+        self.receiver
+            .recv()
+            .await
+            .expect("Worker pool closed unexpectedly.")
+    }
+
+    /// Wait until every worker is idle and take them all out of the pool. The progress is kept
+    /// if the future is dropped, so draining may be resumed later.
+    pub async fn drain(&mut self) {
+        while self.drained < self.size {
+            self.acquire().await;
+            self.drained += 1;
+        }
+    }
+
+    /// Return a worker which has been acquired but not used to the pool.
+    pub fn release(&self, worker: Worker) {
+        self.sender
+            .clone()
+            .try_send(worker)
+            .expect("Failed to release worker.");
+    }
+
+    /// Get a handle for returning workers to the pool.
+    pub fn releaser(&self) -> mpsc::Sender<Worker> {
+        self.sender.clone()
+    }
+}
+
+/// Derive the socket of the worker with index `id` from the configured socket. TCP sockets get
+/// their port shifted by the worker index, other sockets (e.g. IPC paths) get a suffix.
+fn worker_socket(socket: &str, id: i32) -> String {
+    if id == 0 {
+        return socket.to_string();
+    }
+
+    if socket.starts_with("tcp://") {
+        if let Some(index) = socket.rfind(':') {
+            if let Ok(port) = socket[index + 1..].parse::<i32>() {
+                return format!("{}:{}", &socket[..index], port + id);
+            }
+        }
+    }
+
+    format!("{}-{}", socket, id)
+}